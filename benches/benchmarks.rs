@@ -1,7 +1,9 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
 use quorum::ai;
-use quorum::board::Board;
+use quorum::board::{Board, Coord, Color};
+use quorum::movegen::{MoveBuffer, MoveGenerator};
+use im::OrdSet;
 
 pub fn criterion_benchmark(c: &mut Criterion) -> &mut Criterion {
 	{
@@ -15,8 +17,18 @@ pub fn criterion_benchmark(c: &mut Criterion) -> &mut Criterion {
 		(1, Box::new(ai::PieceCountHeuristic {})),
 		(5, Box::new(ai::ConnectedComponentsHeuristic {}))
 	]};
-	c.bench_function("minimax_eval depth 2", |b| b.iter(|| ai::best_move(&board, 2, &heuristic))
-	)
+	c.bench_function("minimax_eval depth 2", |b| b.iter(|| ai::best_move(&board, 2, &heuristic)));
+
+	let midgame = Board::from_position(9, Color::White,
+		OrdSet::from(vec![Coord(0,0), Coord(1,2), Coord(2,2), Coord(3,4), Coord(4,4), Coord(5,3), Coord(6,6), Coord(7,1)]),
+		OrdSet::from(vec![Coord(8,8), Coord(2,3), Coord(3,3), Coord(4,5), Coord(5,5), Coord(6,2), Coord(1,7), Coord(7,7)]));
+	for (name, position) in [("start", &board), ("midgame", &midgame)] {
+		c.bench_function(&format!("moves_of {}", name), |b| b.iter(|| position.moves().count()));
+		c.bench_function(&format!("MoveGenerator {}", name), |b| b.iter(|| MoveGenerator::new(position, position.whose_move).count()));
+		let mut buffer = MoveBuffer::new();
+		c.bench_function(&format!("MoveBuffer::fill {}", name), |b| b.iter(|| buffer.fill(&mut position.compact_moves()).len()));
+	}
+	c
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -6,7 +6,7 @@ use rand_chacha::ChaCha12Rng;
 
 const ZOBRIST_SEED: [u8; 32] = [130, 248, 82, 126, 147, 35, 99, 45, 145, 146, 72, 121, 178, 133, 137, 137, 47, 234, 168, 123, 152, 111, 231, 27, 136, 96, 37, 44, 106, 7, 166, 139];
 const N_PIECE_HASHES: usize = 9*9*2;
-const N_RESERVE_HASHES: usize = 20*2;
+const N_RESERVE_HASHES: usize = 21*2;
 
 fn main() {
     println!("cargo::rerun-if-changed=build.rs");
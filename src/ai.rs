@@ -1,12 +1,104 @@
 use crate::board::*;
+use crate::game::*;
+use crate::movegen::*;
+use itertools::Itertools;
 use std::cmp;
 use std::collections::BinaryHeap;
 use std::sync::RwLock;
+use tinyvec::ArrayVec;
 
-type Valuation = i32;
+pub type Valuation = i32;
+
+/// Heuristics report in hundredths of their natural unit (a piece, a group, a move, a square),
+/// so that weights in a `LinearCombinationHeuristic` compare terms on the same footing.
+pub const CENTI_UNIT: Valuation = 100;
+
+/// Largest magnitude a heuristic reports, leaving `Valuation::MAX` and `MIN` to proven results.
+pub const HEURISTIC_LIMIT: Valuation = Valuation::MAX / 2;
+
+/// `white - black`, in centi-units.
+#[inline]
+pub fn unit_difference(white: usize, black: usize) -> Valuation {
+	clamp_heuristic((white as i64 - black as i64) * CENTI_UNIT as i64)
+}
+
+#[inline]
+pub fn clamp_heuristic(value: i64) -> Valuation {
+	value.clamp(-HEURISTIC_LIMIT as i64, HEURISTIC_LIMIT as i64) as Valuation
+}
+
+/// Converts a measure in whole units to centi-units. NaN counts as even.
+#[inline]
+pub fn centi_units(units: f32) -> Valuation {
+	if units.is_nan() {
+		return 0;
+	}
+	(units as f64 * CENTI_UNIT as f64).clamp(-HEURISTIC_LIMIT as f64, HEURISTIC_LIMIT as f64) as Valuation
+}
+
+/// One term of an evaluation, as reported by `Heuristic::explain`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TermBreakdown {
+	pub name: String,
+	pub value: Valuation,
+	pub weight: Valuation,
+	pub contribution: Valuation
+}
 
 pub trait Heuristic {
 	fn heuristic(&self, board: &Board) -> Valuation;
+
+	/// `heuristic` from the point of view of the side to move, so that higher is better for it.
+	fn relative_heuristic(&self, board: &Board) -> Valuation {
+		match board.whose_move {
+			Color::White => self.heuristic(board),
+			Color::Black => self.heuristic(board).saturating_neg()
+		}
+	}
+
+	/// Name shown in breakdowns: the type name, plus parameters for heuristics that have them.
+	fn name(&self) -> String {
+		let full_name = std::any::type_name::<Self>();
+		let without_generics = full_name.split('<').next().unwrap_or(full_name);
+		without_generics.rsplit("::").next().unwrap_or(without_generics).to_string()
+	}
+
+	/// The terms whose contributions add up to `heuristic(board)`. A heuristic that is not
+	/// a combination of others is a single term of weight 1.
+	fn explain(&self, board: &Board) -> Vec<TermBreakdown> {
+		let value = self.heuristic(board);
+		vec![TermBreakdown { name: self.name(), value, weight: 1, contribution: value }]
+	}
+}
+
+impl<H: Heuristic + ?Sized> Heuristic for &H {
+	fn heuristic(&self, board: &Board) -> Valuation {
+		(**self).heuristic(board)
+	}
+
+	fn name(&self) -> String {
+		(**self).name()
+	}
+
+	fn explain(&self, board: &Board) -> Vec<TermBreakdown> {
+		(**self).explain(board)
+	}
+}
+
+/// Lets an evaluation chosen at run time, such as one with or without a solver extension,
+/// be searched like any other.
+impl<H: Heuristic + ?Sized> Heuristic for Box<H> {
+	fn heuristic(&self, board: &Board) -> Valuation {
+		(**self).heuristic(board)
+	}
+
+	fn name(&self) -> String {
+		(**self).name()
+	}
+
+	fn explain(&self, board: &Board) -> Vec<TermBreakdown> {
+		(**self).explain(board)
+	}
 }
 
 pub const TRANSPOSITION_TABLE_SIZE: usize = 1048576;
@@ -24,6 +116,16 @@ impl TranspositionTable {
 		TranspositionTable { contents }
 	}
 
+	/// Empties every bucket, allocating them first if this is the unallocated static table.
+	pub fn clear(&mut self) {
+		if self.contents.len() != TRANSPOSITION_TABLE_SIZE {
+			*self = TranspositionTable::new();
+		}
+		for bucket in self.contents.iter_mut() {
+			bucket.clear();
+		}
+	}
+
 	#[inline]
 	pub fn add(&mut self, board: &Board, value: Valuation) {
 		self.contents[board.zobrist_hash as usize % TRANSPOSITION_TABLE_SIZE].push((board.zobrist_hash, value));
@@ -46,61 +148,260 @@ impl Default for TranspositionTable {
 
 pub static TRANSPOSITION_TABLE: RwLock<TranspositionTable> = RwLock::new(TranspositionTable { contents: vec![] });
 
-pub fn minimax_eval<H: Heuristic>(board: &Board, depth: i32, heuristic: &H, mut alpha: Valuation, mut beta: Valuation) -> Valuation {
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct SearchStats {
+	pub nodes: u64,
+	pub leaf_evaluations: u64,
+	pub terminal_nodes: u64,
+	pub repetitions: u64,
+	pub tt_probes: u64,
+	pub tt_hits: u64,
+	pub beta_cutoffs: u64,
+	pub first_move_cutoffs: u64,
+	/// Indexed by remaining depth: (nodes expanded, children searched)
+	pub expansions: Vec<(u64, u64)>
+}
+
+impl SearchStats {
+	/// Fraction of beta cutoffs caused by the first move searched; 0 if there were none.
+	pub fn first_move_cutoff_rate(&self) -> f64 {
+		if self.beta_cutoffs == 0 {
+			return 0.0;
+		}
+		self.first_move_cutoffs as f64 / self.beta_cutoffs as f64
+	}
+
+	pub fn branching_factor(&self, depth: usize) -> f64 {
+		match self.expansions.get(depth) {
+			Some(&(expanded, children)) if expanded > 0 => children as f64 / expanded as f64,
+			_ => 0.0
+		}
+	}
+
+	#[inline]
+	fn record_expansion(&mut self, depth: i32, children: u64) {
+		let depth = depth as usize;
+		if self.expansions.len() <= depth {
+			self.expansions.resize(depth + 1, (0, 0));
+		}
+		self.expansions[depth].0 += 1;
+		self.expansions[depth].1 += children;
+	}
+
+	#[inline]
+	fn record_cutoff(&mut self, move_index: u64) {
+		self.beta_cutoffs += 1;
+		if move_index == 0 {
+			self.first_move_cutoffs += 1;
+		}
+	}
+
+	pub fn print(&self) {
+		println!("nodes {} leaves {} terminal {} repetitions {}", self.nodes, self.leaf_evaluations, self.terminal_nodes, self.repetitions);
+		println!("tt probes {} hits {}", self.tt_probes, self.tt_hits);
+		println!("beta cutoffs {} first move cutoff rate {:.3}", self.beta_cutoffs, self.first_move_cutoff_rate());
+		for depth in (1..self.expansions.len()).rev() {
+			println!("depth {depth} expanded {} branching factor {:.2}", self.expansions[depth].0, self.branching_factor(depth));
+		}
+	}
+}
+
+pub const DRAW_VALUATION: Valuation = 0;
+
+pub fn minimax_eval<H: Heuristic>(board: &Board, depth: i32, heuristic: &H, alpha: Valuation, beta: Valuation) -> Valuation {
+	minimax_eval_with_stats(board, depth, heuristic, alpha, beta, &mut vec![], &mut SearchStats::default())
+}
+
+/// `history` holds the Zobrist hashes of the positions leading to `board`. A position that
+/// already occurred there is scored as a draw, since the side that repeated it could do so again.
+pub fn minimax_eval_with_stats<H: Heuristic>(board: &Board, depth: i32, heuristic: &H, alpha: Valuation, beta: Valuation,
+	history: &mut Vec<u64>, stats: &mut SearchStats) -> Valuation {
+	search(board, depth, heuristic, alpha, beta, history, stats).0
+}
+
+/// Also reports whether the value depends on a repetition draw. Such values are only right
+/// for this path through the game, so they are kept out of the transposition table, which
+/// is keyed by position alone.
+fn search<H: Heuristic>(board: &Board, depth: i32, heuristic: &H, mut alpha: Valuation, mut beta: Valuation,
+	history: &mut Vec<u64>, stats: &mut SearchStats) -> (Valuation, bool) {
+	stats.nodes += 1;
+	if history.contains(&board.zobrist_hash) {
+		stats.repetitions += 1;
+		return (DRAW_VALUATION, true);
+	}
 	{
 		let mut transpositions = TRANSPOSITION_TABLE.write().unwrap();
+		stats.tt_probes += 1;
 		if let Some(value) = transpositions.get(board) {
-			return value;
+			stats.tt_hits += 1;
+			return (value, false);
 		} else if depth == 0 {
-			return heuristic.heuristic(board)
+			stats.leaf_evaluations += 1;
+			return (heuristic.heuristic(board), false)
 		} else if depth < 0 {
 			panic!("depth < 0");
 		} else if let Some(winner) = board.winner() {
+			stats.terminal_nodes += 1;
 			let value = match winner {
 				Color::White => Valuation::MAX,
 				Color::Black => Valuation::MIN
 			};
 			transpositions.add(board, value);
-			return value;
+			return (value, false);
 		}
 	}
-	match board.whose_move {
+	let mut children = 0;
+	let mut repetition_dependent = false;
+	history.push(board.zobrist_hash);
+	let mut evaluate = |mov: &Move, alpha: Valuation, beta: Valuation, stats: &mut SearchStats| {
+		let (value, repeated) = search(&board.apply(mov), depth-1, heuristic, alpha, beta, history, stats);
+		repetition_dependent |= repeated;
+		value
+	};
+	let value = match board.whose_move {
 		Color::White => {
 			let mut value = Valuation::MIN;
 			for mov in board.moves() {
-				value = cmp::max(value, minimax_eval(&board.apply(&mov), depth-1, heuristic, alpha, beta));
-				if value >= beta { break; }
+				value = cmp::max(value, evaluate(&mov, alpha, beta, stats));
+				children += 1;
+				if value >= beta {
+					stats.record_cutoff(children - 1);
+					break;
+				}
 				alpha = cmp::max(alpha, value)
 			}
-			TRANSPOSITION_TABLE.write().unwrap().add(board, value);
 			value
 		},
 
 		Color::Black => {
 			let mut value = Valuation::MAX;
 			for mov in board.moves() {
-				value = cmp::min(value, minimax_eval(&board.apply(&mov), depth-1, heuristic, alpha, beta));
-				if value <= alpha { break; }
+				value = cmp::min(value, evaluate(&mov, alpha, beta, stats));
+				children += 1;
+				if value <= alpha {
+					stats.record_cutoff(children - 1);
+					break;
+				}
 				beta = cmp::min(beta, value)
 			}
-			TRANSPOSITION_TABLE.write().unwrap().add(board, value);
 			value
 		}
+	};
+	history.pop();
+	stats.record_expansion(depth, children);
+	if !repetition_dependent {
+		TRANSPOSITION_TABLE.write().unwrap().add(board, value);
 	}
+	(value, repetition_dependent)
 }
 
 pub fn best_move<H: Heuristic>(board: &Board, depth: i32, heuristic: &H) -> Option<Move> {
-	match board.whose_move {
-		Color::White => board.moves().max_by_key(|mov| minimax_eval(&board.apply(mov), depth-1, heuristic, Valuation::MIN, Valuation::MAX)),
-		Color::Black => board.moves().min_by_key(|mov| minimax_eval(&board.apply(mov), depth-1, heuristic, Valuation::MIN, Valuation::MAX))
-	}
+	best_move_with_stats(board, depth, heuristic).0
+}
+
+pub fn best_move_with_stats<H: Heuristic>(board: &Board, depth: i32, heuristic: &H) -> (Option<Move>, SearchStats) {
+	best_move_with_history(board, &[board.zobrist_hash], depth, heuristic)
+}
+
+/// Like `best_move_with_stats`, scoring moves that repeat any position in `history` (the
+/// hashes of the game so far, ending with `board`) as draws.
+pub fn best_move_with_history<H: Heuristic>(board: &Board, history: &[u64], depth: i32, heuristic: &H) -> (Option<Move>, SearchStats) {
+	let mut stats = SearchStats::default();
+	let mut children = 0;
+	let mut path = history.to_vec();
+	let mut evaluate = |mov: &Move| {
+		children += 1;
+		minimax_eval_with_stats(&board.apply(mov), depth-1, heuristic, Valuation::MIN, Valuation::MAX, &mut path, &mut stats)
+	};
+	let mov = match board.whose_move {
+		Color::White => board.moves().max_by_key(|mov| evaluate(mov)),
+		Color::Black => board.moves().min_by_key(|mov| evaluate(mov))
+	};
+	stats.nodes += 1;
+	stats.record_expansion(depth, children);
+	(mov, stats)
 }
 
 pub struct PieceCountHeuristic {}
 
 impl Heuristic for PieceCountHeuristic {
 	fn heuristic(&self, board: &Board) -> Valuation {
-		(board.white.len() as Valuation) - (board.black.len() as Valuation)
+		unit_difference(board.white.len(), board.black.len())
+	}
+}
+
+/// Material with separate values for pieces on the board and in reserve. A color never loses
+/// pieces outright, as captured pieces return to its reserve, so only the difference between
+/// the two values matters: it is what a piece on the board is worth over one in hand.
+pub struct MaterialHeuristic { pub on_board: Valuation, pub in_reserve: Valuation }
+
+impl Default for MaterialHeuristic {
+	fn default() -> Self {
+		MaterialHeuristic { on_board: 2, in_reserve: 1 }
+	}
+}
+
+impl MaterialHeuristic {
+	pub fn material(&self, board: &Board, color: Color) -> Valuation {
+		self.on_board.saturating_mul(board.pieces_of(color).len() as Valuation)
+			.saturating_add(self.in_reserve.saturating_mul(board.reserve_of(color)))
+	}
+}
+
+impl Heuristic for MaterialHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		clamp_heuristic((self.material(board, Color::White) as i64 - self.material(board, Color::Black) as i64) * CENTI_UNIT as i64)
+	}
+
+	fn name(&self) -> String {
+		format!("MaterialHeuristic(on_board={}, in_reserve={})", self.on_board, self.in_reserve)
+	}
+}
+
+/// Values reserve pieces only as far as they can be used: placements on empty squares that
+/// join two or more of one's groups, and conversions available on the next move. Conversions
+/// take priority when the reserve cannot cover both.
+pub struct ReserveHeuristic { pub drop_weight: Valuation, pub conversion_weight: Valuation }
+
+impl Default for ReserveHeuristic {
+	fn default() -> Self {
+		ReserveHeuristic { drop_weight: 1, conversion_weight: 1 }
+	}
+}
+
+impl ReserveHeuristic {
+	/// Empty squares orthogonally next to more than one group of `color`.
+	pub fn joining_drops(board: &Board, color: Color) -> usize {
+		let components = board.components_of(color);
+		board.all_coords()
+			.filter(|coord| !board.is_occupied(*coord))
+			.filter(|coord| {
+				let mut groups: ArrayVec<[usize; 4]> = board.orthogonal_neighborhood(*coord)
+					.filter_map(|neighbor| components.component_of(neighbor))
+					.collect();
+				groups.sort_unstable();
+				groups.iter().dedup().count() > 1
+			})
+			.count()
+	}
+
+	pub fn reserve_value(&self, board: &Board, color: Color) -> Valuation {
+		let reserve = board.reserve_of(color).max(0) as usize;
+		let conversions = cmp::min(reserve, ConvertibleHeuristic::count(board, color.opponent()));
+		let drops = cmp::min(reserve - conversions, ReserveHeuristic::joining_drops(board, color));
+		self.drop_weight.saturating_mul(drops as Valuation).saturating_add(self.conversion_weight.saturating_mul(conversions as Valuation))
+	}
+}
+
+impl Heuristic for ReserveHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		clamp_heuristic((self.reserve_value(board, Color::White) as i64 - self.reserve_value(board, Color::Black) as i64) * CENTI_UNIT as i64)
+	}
+
+	fn name(&self) -> String {
+		format!("ReserveHeuristic(drop_weight={}, conversion_weight={})", self.drop_weight, self.conversion_weight)
 	}
 }
 
@@ -109,9 +410,68 @@ pub struct LegalMovesHeuristic {}
 impl Heuristic for LegalMovesHeuristic {
 	#[inline]
 	fn heuristic(&self, board: &Board) -> Valuation {
-		let white_moves: Vec<_> = board.moves_of(Color::White).collect();
-		let black_moves: Vec<_> = board.moves_of(Color::Black).collect();
-		(white_moves.len() as Valuation) - (black_moves.len() as Valuation)
+		unit_difference(MoveGenerator::new(board, Color::White).count_moves(), MoveGenerator::new(board, Color::Black).count_moves())
+	}
+}
+
+/// Counts empty squares each side can move a piece onto, ignoring conversions and placements.
+pub struct ReachableDestinationsHeuristic {}
+
+impl Heuristic for ReachableDestinationsHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		unit_difference(MoveGenerator::new(board, Color::White).count_destinations(), MoveGenerator::new(board, Color::Black).count_destinations())
+	}
+}
+
+/// Voronoi territory: empty squares strictly closer to one color's pieces than the other's,
+/// with distance measured in king steps through empty squares.
+pub struct TerritoryHeuristic {}
+
+impl TerritoryHeuristic {
+	/// King-step distance from the nearest piece of `color` to every square, through empty squares.
+	pub fn distances(board: &Board, color: Color) -> Vec<u8> {
+		let size = board.board_size;
+		let index = |Coord(x, y): Coord| (x * size + y) as usize;
+		let mut distances = vec![u8::MAX; (size * size) as usize];
+		let mut frontier: Vec<Coord> = board.pieces_of(color).iter().cloned().collect();
+		for coord in frontier.iter() {
+			distances[index(*coord)] = 0;
+		}
+		let mut distance = 0;
+		while !frontier.is_empty() {
+			distance += 1;
+			let mut next = vec![];
+			for coord in frontier {
+				for neighbor in board.neighborhood(coord) {
+					if distances[index(neighbor)] == u8::MAX && !board.is_occupied(neighbor) {
+						distances[index(neighbor)] = distance;
+						next.push(neighbor);
+					}
+				}
+			}
+			frontier = next;
+		}
+		distances
+	}
+}
+
+impl Heuristic for TerritoryHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		let white = TerritoryHeuristic::distances(board, Color::White);
+		let black = TerritoryHeuristic::distances(board, Color::Black);
+		board.all_coords()
+			.filter(|coord| !board.is_occupied(*coord))
+			.map(|Coord(x, y)| {
+				let i = (x * board.board_size + y) as usize;
+				match white[i].cmp(&black[i]) {
+					cmp::Ordering::Less => 1,
+					cmp::Ordering::Greater => -1,
+					cmp::Ordering::Equal => 0
+				}
+			})
+			.sum::<Valuation>() * CENTI_UNIT
 	}
 }
 
@@ -137,7 +497,11 @@ impl Heuristic for CentroidDistanceHeuristic {
 			|Coord(x, y)| ((x as f32 - black_centroid.0).abs() + (y as f32 - black_centroid.1).abs()).powf(self.power)
 		).sum();
 
-		((black_cum_distance - white_cum_distance) * 1000.0) as Valuation
+		centi_units(black_cum_distance - white_cum_distance)
+	}
+
+	fn name(&self) -> String {
+		format!("CentroidDistanceHeuristic(power={})", self.power)
 	}
 }
 
@@ -146,22 +510,7 @@ pub struct ConnectedComponentsHeuristic {}
 impl Heuristic for ConnectedComponentsHeuristic {
 	#[inline]
 	fn heuristic(&self, board: &Board) -> Valuation {
-		let mut white_pieces = board.white.clone();
-		let mut n_white_components = 0;
-		while !white_pieces.is_empty() {
-			let source = *white_pieces.iter().next().unwrap();
-			n_white_components += 1;
-			white_pieces = white_pieces.difference(board.flood_fill(Color::White, source));
-		}
-		let mut black_pieces = board.black.clone();
-		let mut n_black_components = 0;
-		while !black_pieces.is_empty() {
-			let source = *black_pieces.iter().next().unwrap();
-			n_black_components += 1;
-			black_pieces = black_pieces.difference(board.flood_fill(Color::Black, source));
-		}
-
-		n_black_components as Valuation - n_white_components as Valuation
+		unit_difference(board.component_count(Color::Black), board.component_count(Color::White))
 	}
 }
 
@@ -170,33 +519,168 @@ pub struct NthSmallestStringHeuristic { pub n: usize }
 impl Heuristic for NthSmallestStringHeuristic {
 	#[inline]
 	fn heuristic(&self, board: &Board) -> Valuation {
-		let mut white_sizes = BinaryHeap::new();
-		let mut white_pieces = board.white.clone();
-		while !white_pieces.is_empty() {
-			let source = *white_pieces.iter().next().unwrap();
-			let string = board.flood_fill(Color::White, source);
-			white_sizes.push(string.len());
-			white_pieces = white_pieces.difference(string);
-		}
+		let mut white_sizes: BinaryHeap<_> = board.components_of(Color::White).sizes().collect();
 		for _ in 0..(self.n as Valuation - 1) {
 			white_sizes.pop();
 		}
 		let white_nth_smallest = white_sizes.pop().unwrap_or(0);
 
-		let mut black_sizes = BinaryHeap::new();
-		let mut black_pieces = board.black.clone();
-		while !black_pieces.is_empty() {
-			let source = *black_pieces.iter().next().unwrap();
-			let string = board.flood_fill(Color::Black, source);
-			black_sizes.push(string.len());
-			black_pieces = black_pieces.difference(string);
-		}
+		let mut black_sizes: BinaryHeap<_> = board.components_of(Color::Black).sizes().collect();
 		for _ in 0..(self.n as Valuation - 1) {
 			black_sizes.pop();
 		}
 		let black_nth_smallest = black_sizes.pop().unwrap_or(0);
 
-		black_nth_smallest as Valuation - white_nth_smallest as Valuation
+		unit_difference(black_nth_smallest, white_nth_smallest)
+	}
+
+	fn name(&self) -> String {
+		format!("NthSmallestStringHeuristic(n={})", self.n)
+	}
+}
+
+/// Estimated moves each color needs to join all its groups: a minimum spanning tree over the
+/// groups, where joining two groups costs the empty squares on the shortest orthogonal path
+/// between them, and one move can close up to `max_gap + 1` of those squares.
+pub struct ConnectionDistanceHeuristic {}
+
+impl ConnectionDistanceHeuristic {
+	pub fn connection_cost(board: &Board, color: Color) -> Valuation {
+		let components = board.components_of(color);
+		let mut ids: Vec<usize> = board.pieces_of(color).iter().filter_map(|coord| components.component_of(*coord)).collect();
+		ids.sort_unstable();
+		ids.dedup();
+		if ids.len() <= 1 {
+			return 0;
+		}
+
+		let size = board.board_size;
+		let index = |Coord(x, y): Coord| (x * size + y) as usize;
+		let mut gaps = vec![vec![u32::MAX; ids.len()]; ids.len()];
+		for (i, id) in ids.iter().enumerate() {
+			let mut seen = vec![false; (size * size) as usize];
+			let mut frontier: Vec<Coord> = components.members(*id).collect();
+			for coord in frontier.iter() {
+				seen[index(*coord)] = true;
+			}
+			let mut empties = 0;
+			while !frontier.is_empty() {
+				let mut next = vec![];
+				for coord in frontier {
+					for neighbor in board.orthogonal_neighborhood(coord).filter(|neighbor| board.in_bounds(*neighbor)) {
+						if seen[index(neighbor)] {
+							continue;
+						}
+						seen[index(neighbor)] = true;
+						if !board.is_occupied(neighbor) {
+							next.push(neighbor);
+						} else if let Some(other) = components.component_of(neighbor).filter(|_| board.pieces_of(color).contains(&neighbor)) {
+							let j = ids.binary_search(&other).unwrap();
+							gaps[i][j] = cmp::min(gaps[i][j], empties);
+						}
+					}
+				}
+				frontier = next;
+				empties += 1;
+			}
+		}
+
+		// Prim's algorithm; groups walled off from the rest cost as much as the board has squares
+		let reach = (board.max_gap + 1) as u32;
+		let moves = |gap: u32| if gap == u32::MAX { (size * size) as u32 } else { gap.div_ceil(reach) };
+		let mut in_tree = vec![false; ids.len()];
+		let mut best: Vec<u32> = gaps[0].iter().map(|&gap| moves(gap)).collect();
+		in_tree[0] = true;
+		let mut cost = 0;
+		for _ in 1..ids.len() {
+			let next = (0..ids.len()).filter(|&j| !in_tree[j]).min_by_key(|&j| best[j]).unwrap();
+			in_tree[next] = true;
+			cost += best[next];
+			for j in 0..ids.len() {
+				best[j] = cmp::min(best[j], moves(gaps[next][j]));
+			}
+		}
+		cost as Valuation
+	}
+}
+
+impl Heuristic for ConnectionDistanceHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		(ConnectionDistanceHeuristic::connection_cost(board, Color::Black)
+			- ConnectionDistanceHeuristic::connection_cost(board, Color::White)) * CENTI_UNIT
+	}
+}
+
+/// Pieces with at most `max_liberties` empty squares around them, which are the easiest to surround.
+pub struct FewLibertiesHeuristic { pub max_liberties: usize }
+
+impl FewLibertiesHeuristic {
+	pub fn count(&self, board: &Board, color: Color) -> usize {
+		board.pieces_of(color).iter()
+			.filter(|coord| board.neighborhood(**coord).filter(|liberty| !board.is_occupied(*liberty)).count() <= self.max_liberties)
+			.count()
+	}
+}
+
+impl Heuristic for FewLibertiesHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		unit_difference(self.count(board, Color::Black), self.count(board, Color::White))
+	}
+
+	fn name(&self) -> String {
+		format!("FewLibertiesHeuristic(max_liberties={})", self.max_liberties)
+	}
+}
+
+/// Pieces of `color` that some movement of the opponent puts in `at_risk(board, opponent, active, dest)`.
+fn pieces_at_risk<'a, I: Iterator<Item=Coord> + 'a>(board: &'a Board, color: Color, at_risk: impl Fn(&'a Board, Color, Coord, Coord) -> I) -> usize {
+	let mut pieces: Vec<Coord> = MoveGenerator::new(board, color.opponent()).movement_targets().into_iter()
+		.flat_map(|(active, dest)| at_risk(board, color.opponent(), active, dest))
+		.collect();
+	pieces.sort_unstable();
+	pieces.dedup();
+	pieces.len()
+}
+
+/// Pieces the opponent could capture with their next move.
+pub struct CapturableHeuristic {}
+
+impl CapturableHeuristic {
+	pub fn count(board: &Board, color: Color) -> usize {
+		pieces_at_risk(board, color, Board::capturable_around)
+	}
+}
+
+impl Heuristic for CapturableHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		unit_difference(CapturableHeuristic::count(board, Color::Black), CapturableHeuristic::count(board, Color::White))
+	}
+}
+
+/// Pieces the opponent could convert with their next move. Those not converted for lack of
+/// reserve are taken anyway, so the opponent's reserve is not considered. A flanked piece that
+/// the same move captures cannot be converted, so it is counted only by `CapturableHeuristic`.
+pub struct ConvertibleHeuristic {}
+
+impl ConvertibleHeuristic {
+	pub fn count(board: &Board, color: Color) -> usize {
+		pieces_at_risk(board, color, |board, opponent, active, dest| {
+			let capturable: Vec<_> = board.capturable_around(opponent, active, dest).collect();
+			board.convertible_around(opponent, active, dest)
+				.filter(|coord| !capturable.contains(coord))
+				.collect::<Vec<_>>()
+				.into_iter()
+		})
+	}
+}
+
+impl Heuristic for ConvertibleHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		unit_difference(ConvertibleHeuristic::count(board, Color::Black), ConvertibleHeuristic::count(board, Color::White))
 	}
 }
 
@@ -204,14 +688,32 @@ pub struct LinearCombinationHeuristic {
 	pub terms: Vec<(Valuation, Box<dyn Heuristic>)>
 }
 
+impl LinearCombinationHeuristic {
+	pub fn set_weights(&mut self, weights: &[Valuation]) {
+		assert_eq!(weights.len(), self.terms.len(), "Expected one weight per term");
+		for ((weight, _), new_weight) in self.terms.iter_mut().zip(weights) {
+			*weight = *new_weight;
+		}
+	}
+}
+
 impl Heuristic for LinearCombinationHeuristic {
 	#[inline]
 	fn heuristic(&self, board: &Board) -> Valuation {
-		let mut sum = 0;
+		let mut sum: i64 = 0;
 		for (weight, subheuristic) in self.terms.iter() {
-			sum += weight * subheuristic.heuristic(board);
+			sum = sum.saturating_add(*weight as i64 * subheuristic.heuristic(board) as i64);
 		}
-		sum
+		clamp_heuristic(sum)
+	}
+
+	/// Contributions are clamped one by one, so their sum can differ from `heuristic` only
+	/// when the total is out of range.
+	fn explain(&self, board: &Board) -> Vec<TermBreakdown> {
+		self.terms.iter().map(|(weight, subheuristic)| {
+			let value = subheuristic.heuristic(board);
+			TermBreakdown { name: subheuristic.name(), value, weight: *weight, contribution: clamp_heuristic(*weight as i64 * value as i64) }
+		}).collect()
 	}
 }
 
@@ -219,11 +721,12 @@ pub fn playout<H: Heuristic>(heuristic: H, root: &Board) -> Option<Color> {
 	// from root
 	// get legal moves
 	// choose one
-	// repeat until no children (until winner)
+	// repeat until no children (until the game is decided or drawn)
 	//
-	let mut board = root.clone();
-	let mut ply = 0;
-	while board.winner().is_none() {
+	let rules = DrawRules { max_plies: Some(1000), ..DrawRules::default() };
+	let mut game = Game::new(root.clone(), rules);
+	while game.outcome() == GameOutcome::Ongoing {
+		let board = game.board();
 		let mut moves: Vec<_> = board.moves().collect();
 		let goal = match board.whose_move { Color::White => 1, Color::Black => -1 };
 		moves.sort_by_cached_key(|x| goal * -heuristic.heuristic(&board.apply(x)));
@@ -232,17 +735,210 @@ pub fn playout<H: Heuristic>(heuristic: H, root: &Board) -> Option<Color> {
 			board.show_board();
 			panic!();
 		}
-		board = board.apply(&moves[0]);
+		game.play(&moves[0]);
 
-		ply += 1;
-		#[allow(clippy::modulo_one)]
+		let ply = game.ply();
+		#[allow(clippy::modulo_one, clippy::manual_is_multiple_of)]
 		if ply % 1 == 0 {
-			println!("Turn {} Heuristic {}", ply, heuristic.heuristic(&board));
-			board.show_board();
+			println!("Turn {} Heuristic {}", ply, heuristic.heuristic(game.board()));
+			game.board().show_board();
+		}
+	}
+	println!("{} {:?}", game.ply(), game.outcome());
+	game.board().show_board();
+	game.board().winner()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::symmetry::*;
+	use im::OrdSet;
+
+	#[test]
+	fn mobility_and_territory() {
+		let board = Board::start_position(9);
+		let white_moves = board.moves_of(Color::White).count() as Valuation;
+		let black_moves = board.moves_of(Color::Black).count() as Valuation;
+		assert_eq!((white_moves - black_moves) * CENTI_UNIT, LegalMovesHeuristic {}.heuristic(&board));
+		assert_eq!(0, ReachableDestinationsHeuristic {}.heuristic(&board));
+		assert_eq!(0, TerritoryHeuristic {}.heuristic(&board));
+
+		// A white wall closes off the two rows behind it and is nearer most of the rest
+		let white: OrdSet<Coord> = (0..9).map(|y| Coord(2, y)).collect();
+		let black = OrdSet::unit(Coord(8, 8));
+		let board = Board::from_position(9, Color::White, white, black);
+		assert_eq!(3600, TerritoryHeuristic {}.heuristic(&board));
+		let flipped = board.transform(Symmetry::Rotate90);
+		assert_eq!(TerritoryHeuristic {}.heuristic(&board), -TerritoryHeuristic {}.heuristic(&flipped));
+	}
+
+	#[test]
+	fn connection_distance() {
+		let black = OrdSet::from(vec![Coord(8,8), Coord(8,7)]);
+		let cost = |white: Vec<Coord>| {
+			let board = Board::from_position(9, Color::White, OrdSet::from(white), black.clone());
+			ConnectionDistanceHeuristic {}.heuristic(&board)
+		};
+		assert_eq!(0, cost(vec![Coord(0,0), Coord(0,1)]));
+		assert_eq!(-100, cost(vec![Coord(0,0), Coord(0,4)]));
+		assert_eq!(-200, cost(vec![Coord(0,0), Coord(0,5)]));
+		// The third group joins the nearer of the other two
+		assert_eq!(-300, cost(vec![Coord(0,0), Coord(0,5), Coord(2,5)]));
+
+		// Walled into the corner by black
+		let white = OrdSet::from(vec![Coord(0,0), Coord(4,4)]);
+		let black = OrdSet::from(vec![Coord(0,1), Coord(1,0), Coord(1,1)]);
+		let board = Board::from_position(9, Color::White, white, black);
+		assert_eq!(81, ConnectionDistanceHeuristic::connection_cost(&board, Color::White));
+	}
+
+	#[test]
+	fn vulnerability() {
+		// Black's corner piece is taken if White lands on (1,0)
+		let white = OrdSet::from(vec![Coord(0,1), Coord(1,1), Coord(1,2)]);
+		let black = OrdSet::from(vec![Coord(0,0), Coord(8,8)]);
+		let board = Board::from_position(9, Color::White, white, black);
+		assert_eq!(100, FewLibertiesHeuristic { max_liberties: 1 }.heuristic(&board));
+		assert_eq!(100, CapturableHeuristic {}.heuristic(&board));
+		assert_eq!(0, ConvertibleHeuristic {}.heuristic(&board));
+
+		// Black's (4,4) is flanked if White lands on (4,5)
+		let white = OrdSet::from(vec![Coord(4,3), Coord(4,6), Coord(4,7)]);
+		let black = OrdSet::from(vec![Coord(4,4), Coord(0,8)]);
+		let board = Board::from_position(9, Color::White, white, black);
+		assert_eq!(0, FewLibertiesHeuristic { max_liberties: 1 }.heuristic(&board));
+		assert_eq!(0, CapturableHeuristic {}.heuristic(&board));
+		assert_eq!(100, ConvertibleHeuristic {}.heuristic(&board));
+		assert_eq!(-100, ConvertibleHeuristic {}.heuristic(&board.transform(Symmetry::Rotate90)));
+
+		// Black's (0,1) is flanked if White lands on (0,2), but that move captures it
+		let white = OrdSet::from(vec![Coord(0,4), Coord(0,3), Coord(0,0), Coord(1,0), Coord(1,1), Coord(1,2)]);
+		let black = OrdSet::from(vec![Coord(0,1), Coord(8,8)]);
+		let mut board = Board::from_position(9, Color::White, white, black);
+		assert_eq!(1, CapturableHeuristic::count(&board, Color::Black));
+		assert_eq!(0, ConvertibleHeuristic::count(&board, Color::Black));
+		board.white_reserve = 1;
+		assert_eq!(0, ReserveHeuristic { drop_weight: 0, conversion_weight: 1 }.reserve_value(&board, Color::White));
+	}
+
+	#[test]
+	fn reserve_and_material() {
+		let white = OrdSet::from(vec![Coord(4,3), Coord(4,5), Coord(4,6), Coord(2,7)]);
+		let black = OrdSet::from(vec![Coord(3,7), Coord(8,0)]);
+		let mut board = Board::from_position(9, Color::White, white, black);
+		assert_eq!(0, MaterialHeuristic { on_board: 1, in_reserve: 1 }.heuristic(&board));
+		assert_eq!(2 * (4 - 2) * CENTI_UNIT, MaterialHeuristic { on_board: 3, in_reserve: 1 }.heuristic(&board));
+
+		// (4,4) joins two white groups, and moving (4,3) over (4,5) converts (3,7)
+		assert_eq!(1, ReserveHeuristic::joining_drops(&board, Color::White));
+		assert_eq!(0, ReserveHeuristic::joining_drops(&board, Color::Black));
+		assert_eq!(200, ReserveHeuristic::default().heuristic(&board));
+		board.white_reserve = 1;
+		assert_eq!(300, ReserveHeuristic { drop_weight: 1, conversion_weight: 3 }.heuristic(&board));
+		board.white_reserve = 0;
+		assert_eq!(0, ReserveHeuristic::default().heuristic(&board));
+	}
+
+	#[test]
+	fn explain_breakdown() {
+		let heuristic = LinearCombinationHeuristic { terms: vec![
+			(1, Box::new(CentroidDistanceHeuristic { power: 2.0 })),
+			(3, Box::new(PieceCountHeuristic {})),
+			(-5, Box::new(ConnectedComponentsHeuristic {}))
+		]};
+		let white = OrdSet::from(vec![Coord(0,0), Coord(2,0), Coord(4,0)]);
+		let black = OrdSet::from(vec![Coord(8,8)]);
+		let board = Board::from_position(9, Color::White, white, black);
+		let breakdown = heuristic.explain(&board);
+		let names: Vec<_> = breakdown.iter().map(|term| term.name.as_str()).collect();
+		assert_eq!(vec!["CentroidDistanceHeuristic(power=2)", "PieceCountHeuristic", "ConnectedComponentsHeuristic"], names);
+		assert_eq!(TermBreakdown { name: "PieceCountHeuristic".to_string(), value: 200, weight: 3, contribution: 600 }, breakdown[1]);
+		assert_eq!(TermBreakdown { name: "ConnectedComponentsHeuristic".to_string(), value: -200, weight: -5, contribution: 1000 }, breakdown[2]);
+		assert_eq!(heuristic.heuristic(&board), breakdown.iter().map(|term| term.contribution).sum::<Valuation>());
+		assert_eq!(vec![TermBreakdown { name: "PieceCountHeuristic".to_string(), value: 200, weight: 1, contribution: 200 }],
+			PieceCountHeuristic {}.explain(&board));
+	}
+
+	#[test]
+	fn extreme_positions_stay_in_range() {
+		let all_coords: Vec<Coord> = Board::start_position(9).all_coords().collect();
+		let spread = |coords: &[Coord]| coords.iter().cloned().step_by(4).take(20).collect::<OrdSet<Coord>>();
+		let mut boards = vec![
+			Board::from_position(9, Color::White, spread(&all_coords), OrdSet::new()),
+			Board::from_position(9, Color::Black, OrdSet::new(), spread(&all_coords[1..])),
+			Board::from_position(9, Color::White, spread(&all_coords), spread(&all_coords[2..]))
+		];
+		let mut huge_reserve = boards[2].clone();
+		huge_reserve.white_reserve = Valuation::MAX;
+		huge_reserve.black_reserve = Valuation::MIN;
+		boards.push(huge_reserve);
+
+		let extremes: Vec<Box<dyn Heuristic>> = vec![
+			Box::new(CentroidDistanceHeuristic { power: 1000.0 }),
+			Box::new(CentroidDistanceHeuristic { power: -1000.0 }),
+			Box::new(MaterialHeuristic { on_board: Valuation::MAX, in_reserve: Valuation::MIN }),
+			Box::new(ReserveHeuristic { drop_weight: Valuation::MAX, conversion_weight: Valuation::MAX }),
+			Box::new(NthSmallestStringHeuristic { n: usize::MAX })
+		];
+		let mut everything = LinearCombinationHeuristic { terms: vec![] };
+		for info in crate::registry::REGISTRY {
+			let defaults: Vec<f64> = info.parameters.iter().map(|parameter| parameter.default).collect();
+			everything.terms.push((Valuation::MAX, (info.build)(&defaults)));
+			everything.terms.push((Valuation::MIN, (info.build)(&defaults)));
+		}
+		for board in boards.iter() {
+			for heuristic in extremes.iter() {
+				assert!(heuristic.heuristic(board).abs() <= HEURISTIC_LIMIT);
+			}
+			for (_, heuristic) in everything.terms.iter() {
+				assert!(heuristic.heuristic(board).abs() <= HEURISTIC_LIMIT);
+				let sign = if board.whose_move == Color::White { 1 } else { -1 };
+				assert_eq!(sign * heuristic.heuristic(board), heuristic.relative_heuristic(board));
+			}
+			assert!(everything.heuristic(board).abs() <= HEURISTIC_LIMIT);
+		}
+		let one_sided = LinearCombinationHeuristic { terms: vec![(Valuation::MAX, Box::new(PieceCountHeuristic {})), (Valuation::MAX, Box::new(PieceCountHeuristic {}))] };
+		assert_eq!(HEURISTIC_LIMIT, one_sided.heuristic(&boards[0]));
+		assert_eq!(-HEURISTIC_LIMIT, one_sided.heuristic(&boards[1]));
+		assert_eq!(HEURISTIC_LIMIT, one_sided.relative_heuristic(&boards[0]));
+		assert_eq!(HEURISTIC_LIMIT, one_sided.relative_heuristic(&boards[1]));
+	}
+
+	#[test]
+	fn search_stats_rates() {
+		let mut stats = SearchStats::default();
+		assert_eq!(0.0, stats.first_move_cutoff_rate());
+		assert_eq!(0.0, stats.branching_factor(1));
+		stats.record_cutoff(0);
+		stats.record_cutoff(3);
+		assert_eq!(0.5, stats.first_move_cutoff_rate());
+		stats.record_expansion(1, 6);
+		stats.record_expansion(1, 2);
+		assert_eq!(4.0, stats.branching_factor(1));
+	}
+
+	struct ConstantHeuristic(Valuation);
+
+	impl Heuristic for ConstantHeuristic {
+		fn heuristic(&self, _board: &Board) -> Valuation {
+			self.0
+		}
+	}
+
+	#[test]
+	fn repetition_draws_stay_out_of_table() {
+		let white = OrdSet::from(vec![Coord(0,0), Coord(0,2), Coord(4,4)]);
+		let black = OrdSet::from(vec![Coord(8,8), Coord(8,6), Coord(6,3)]);
+		let board = Board::from_position(9, Color::Black, white, black);
+		let heuristic = ConstantHeuristic(500);
+		let repeated = board.apply(&board.moves().next().unwrap());
+		for depth in [1, 2] {
+			TRANSPOSITION_TABLE.write().unwrap().clear();
+			let mut history = vec![repeated.zobrist_hash];
+			let with_repetition = minimax_eval_with_stats(&board, depth, &heuristic, Valuation::MIN, Valuation::MAX, &mut history, &mut SearchStats::default());
+			assert_eq!(DRAW_VALUATION, with_repetition);
+			assert_eq!(500, minimax_eval(&board, depth, &heuristic, Valuation::MIN, Valuation::MAX));
 		}
-		if ply > 1000 { break; }
 	}
-	println!("{}", ply);
-	board.show_board();
-	board.winner()
 }
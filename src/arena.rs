@@ -0,0 +1,388 @@
+use crate::ai::*;
+use crate::board::*;
+use crate::book::*;
+use crate::game::*;
+use crate::notation::*;
+use crate::solver::*;
+
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::time::{Duration, Instant};
+
+pub struct EngineConfig {
+	pub name: String,
+	pub heuristic: LinearCombinationHeuristic,
+	pub depth: i32,
+	/// With a budget, searches deepen one ply at a time up to `depth` and start no new
+	/// iteration once the budget is spent, so a move can take longer than the budget
+	pub time_budget: Option<Duration>,
+	pub book: Option<OpeningBook>,
+	/// Proves short forced wins at the leaves of the search
+	pub solver: Option<SolverSettings>
+}
+
+impl EngineConfig {
+	fn search(&self, game: &Game, depth: i32) -> Option<Move> {
+		match self.solver {
+			Some(solver) => best_move_with_history(game.board(), game.history(), depth, &solver.extend(&self.heuristic)).0,
+			None => best_move_with_history(game.board(), game.history(), depth, &self.heuristic).0
+		}
+	}
+
+	pub fn choose_move<R: RngCore>(&self, game: &Game, rng: &mut R) -> Option<Move> {
+		if let Some(mov) = self.book.as_ref().and_then(|book| book.choose(game.board(), rng)) {
+			return Some(mov);
+		}
+		let Some(budget) = self.time_budget else {
+			// The table is shared between engines and stores values from one heuristic only
+			TRANSPOSITION_TABLE.write().unwrap().clear();
+			return self.search(game, self.depth);
+		};
+		let start = Instant::now();
+		let mut best = None;
+		for depth in 1..=self.depth {
+			// Table entries carry no depth, so a shallower iteration's values must not be reused
+			TRANSPOSITION_TABLE.write().unwrap().clear();
+			best = self.search(game, depth);
+			if start.elapsed() >= budget {
+				break;
+			}
+		}
+		best
+	}
+}
+
+/// One side of a match as written on the command line: `;`-separated `key=value` settings
+/// `heuristic`, `weights`, `depth`, `time` (milliseconds per move), `book` and `solver`, whose
+/// value holds `SolverSettings` such as `plies=3, max_stragglers=1` and may be empty for the
+/// defaults. An entry without `=` is a weights file, so a plain path still names an engine.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EngineSpec {
+	pub heuristic: String,
+	pub weights: Option<String>,
+	pub depth: i32,
+	pub time_budget: Option<Duration>,
+	pub book: Option<String>,
+	pub solver: Option<SolverSettings>
+}
+
+impl EngineSpec {
+	pub const DEFAULT_DEPTH: i32 = 2;
+
+	pub fn parse(spec: &str, default_heuristic: &str) -> Result<EngineSpec, String> {
+		let mut engine = EngineSpec { heuristic: default_heuristic.to_string(), weights: None, depth: Self::DEFAULT_DEPTH, time_budget: None, book: None, solver: None };
+		for setting in spec.split(';').map(str::trim).filter(|setting| !setting.is_empty()) {
+			let Some((key, value)) = setting.split_once('=') else {
+				engine.weights = Some(setting.to_string());
+				continue;
+			};
+			match key.trim() {
+				"heuristic" => engine.heuristic = value.to_string(),
+				"weights" => engine.weights = Some(value.to_string()),
+				"depth" => engine.depth = value.parse().ok().filter(|&depth| depth >= 1)
+					.ok_or_else(|| format!("Depth must be a positive whole number, not {value:?}"))?,
+				"time" => engine.time_budget = Some(Duration::from_millis(value.parse()
+					.map_err(|_| format!("Time must be a whole number of milliseconds, not {value:?}"))?)),
+				"book" => engine.book = Some(value.to_string()),
+				"solver" => engine.solver = Some(SolverSettings::parse(value)?),
+				_ => return Err(format!("Unknown engine setting {key:?}"))
+			}
+		}
+		Ok(engine)
+	}
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GameScore { Win, Draw, Loss }
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SprtConfig {
+	pub elo0: f64,
+	pub elo1: f64,
+	pub alpha: f64,
+	pub beta: f64
+}
+
+impl Default for SprtConfig {
+	fn default() -> Self {
+		SprtConfig { elo0: 0.0, elo1: 10.0, alpha: 0.05, beta: 0.05 }
+	}
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SprtStatus { AcceptH0, AcceptH1, Continue }
+
+impl SprtConfig {
+	pub fn lower_bound(&self) -> f64 {
+		(self.beta / (1.0 - self.alpha)).ln()
+	}
+
+	pub fn upper_bound(&self) -> f64 {
+		((1.0 - self.beta) / self.alpha).ln()
+	}
+}
+
+/// Expected score of the stronger side for a given Elo difference.
+#[inline]
+pub fn elo_to_score(elo: f64) -> f64 {
+	1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[inline]
+pub fn score_to_elo(score: f64) -> f64 {
+	-400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Win/draw/loss counts from the point of view of the first engine.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct MatchResult {
+	pub wins: u32,
+	pub draws: u32,
+	pub losses: u32
+}
+
+impl MatchResult {
+	pub fn add(&mut self, score: GameScore) {
+		match score {
+			GameScore::Win => self.wins += 1,
+			GameScore::Draw => self.draws += 1,
+			GameScore::Loss => self.losses += 1
+		}
+	}
+
+	pub fn games(&self) -> u32 {
+		self.wins + self.draws + self.losses
+	}
+
+	/// Fraction of the points won; 0.5 before any game is played.
+	pub fn score(&self) -> f64 {
+		if self.games() == 0 {
+			return 0.5;
+		}
+		(self.wins as f64 + 0.5 * self.draws as f64) / self.games() as f64
+	}
+
+	/// Keeps a score half a game away from 0 and 1, where the Elo difference is infinite.
+	fn finite_score(&self, score: f64) -> f64 {
+		let margin = 0.5 / (self.games() + 1) as f64;
+		score.clamp(margin, 1.0 - margin)
+	}
+
+	/// Variance of the score of a single game.
+	pub fn variance(&self) -> f64 {
+		if self.games() == 0 {
+			return 0.0;
+		}
+		let games = self.games() as f64;
+		let score = self.score();
+		(self.wins as f64 * (1.0 - score).powi(2)
+			+ self.draws as f64 * (0.5 - score).powi(2)
+			+ self.losses as f64 * score.powi(2)) / games
+	}
+
+	pub fn elo(&self) -> f64 {
+		score_to_elo(self.finite_score(self.score()))
+	}
+
+	/// Elo bounds of the 95% confidence interval of the score.
+	pub fn elo_interval(&self) -> (f64, f64) {
+		let margin = 1.96 * (self.variance() / self.games().max(1) as f64).sqrt();
+		let score = self.score();
+		(score_to_elo(self.finite_score(score - margin)), score_to_elo(self.finite_score(score + margin)))
+	}
+
+	/// Log-likelihood ratio of H1 (difference is `elo1`) against H0 (difference is `elo0`),
+	/// using the normal approximation to the trinomial distribution.
+	pub fn llr(&self, sprt: &SprtConfig) -> f64 {
+		let variance = self.variance();
+		if self.games() == 0 || variance == 0.0 {
+			return 0.0;
+		}
+		let score0 = elo_to_score(sprt.elo0);
+		let score1 = elo_to_score(sprt.elo1);
+		self.games() as f64 * (score1 - score0) * (2.0 * self.score() - score0 - score1) / (2.0 * variance)
+	}
+
+	pub fn sprt_status(&self, sprt: &SprtConfig) -> SprtStatus {
+		let llr = self.llr(sprt);
+		if llr >= sprt.upper_bound() {
+			SprtStatus::AcceptH1
+		} else if llr <= sprt.lower_bound() {
+			SprtStatus::AcceptH0
+		} else {
+			SprtStatus::Continue
+		}
+	}
+}
+
+pub struct Arena {
+	pub opening_plies: usize,
+	pub rules: DrawRules,
+	pub max_games: u32,
+	pub seed: u64,
+	pub sprt: Option<SprtConfig>
+}
+
+impl Default for Arena {
+	fn default() -> Self {
+		Arena { opening_plies: 4, rules: DrawRules { max_plies: Some(200), ..DrawRules::default() }, max_games: 100, seed: 0, sprt: Some(SprtConfig::default()) }
+	}
+}
+
+impl Arena {
+	/// Plays `opening_plies` uniformly random moves in `game`, none of them winning outright.
+	pub fn play_random_opening(&self, game: &mut Game, rng: &mut ChaCha12Rng) {
+		for _ in 0..self.opening_plies {
+			let board = game.board();
+			let moves: Vec<_> = board.moves()
+				.filter(|mov| board.apply(mov).winner().is_none())
+				.collect();
+			if moves.is_empty() {
+				break;
+			}
+			game.play(&moves[rng.next_u64() as usize % moves.len()]);
+		}
+	}
+
+	/// The position after `play_random_opening` from the start position.
+	pub fn random_opening(&self, rng: &mut ChaCha12Rng) -> Board {
+		let mut game = Game::new(Board::start_position(9), self.rules);
+		self.play_random_opening(&mut game, rng);
+		game.board().clone()
+	}
+
+	/// Plays one game from `opening`, returning the score of the engine playing White.
+	pub fn play_game(&self, opening: &Board, white: &EngineConfig, black: &EngineConfig, rng: &mut ChaCha12Rng) -> GameScore {
+		let mut game = Game::new(opening.clone(), self.rules);
+		loop {
+			match game.outcome() {
+				GameOutcome::Win(Color::White) => return GameScore::Win,
+				GameOutcome::Win(Color::Black) => return GameScore::Loss,
+				GameOutcome::Draw(_) => return GameScore::Draw,
+				GameOutcome::Ongoing => ()
+			}
+			let engine = match game.board().whose_move { Color::White => white, Color::Black => black };
+			let Some(mov) = engine.choose_move(&game, rng) else { return GameScore::Draw };
+			game.play(&mov);
+		}
+	}
+
+	/// Plays `engine` against itself after `opening_plies` random moves, recording the game
+	/// from the start position so it can be replayed or used for training.
+	pub fn self_play_record(&self, engine: &EngineConfig, rng: &mut ChaCha12Rng) -> GameRecord {
+		let mut game = Game::new(Board::start_position(9), self.rules);
+		self.play_random_opening(&mut game, rng);
+		while game.outcome() == GameOutcome::Ongoing {
+			let Some(mov) = engine.choose_move(&game, rng) else { break };
+			game.play(&mov);
+		}
+		game.to_record().expect("Self-play starts from the start position")
+	}
+
+	/// Plays pairs of games from shared random openings with colors alternated, calling
+	/// `report` with the result so far and the opening after every game, until `max_games` is
+	/// reached or the SPRT concludes.
+	pub fn run<F: FnMut(&MatchResult, &Board)>(&self, first: &EngineConfig, second: &EngineConfig, mut report: F) -> MatchResult {
+		let mut rng = ChaCha12Rng::seed_from_u64(self.seed);
+		let mut result = MatchResult::default();
+		let mut opening = self.random_opening(&mut rng);
+		while result.games() < self.max_games {
+			if result.games() % 2 == 0 {
+				result.add(self.play_game(&opening, first, second, &mut rng));
+				report(&result, &opening);
+			} else {
+				result.add(match self.play_game(&opening, second, first, &mut rng) {
+					GameScore::Win => GameScore::Loss,
+					GameScore::Draw => GameScore::Draw,
+					GameScore::Loss => GameScore::Win
+				});
+				report(&result, &opening);
+				opening = self.random_opening(&mut rng);
+			}
+			if let Some(sprt) = &self.sprt {
+				if result.sprt_status(sprt) != SprtStatus::Continue {
+					break;
+				}
+			}
+		}
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::registry::*;
+
+	#[test]
+	fn elo_from_score() {
+		let even = MatchResult { wins: 10, draws: 5, losses: 10 };
+		assert!(even.elo().abs() < 1e-9);
+		let (low, high) = even.elo_interval();
+		assert!(low < 0.0 && high > 0.0);
+
+		let ahead = MatchResult { wins: 75, draws: 0, losses: 25 };
+		assert!((ahead.elo() - 190.85).abs() < 0.01);
+		assert!((elo_to_score(ahead.elo()) - 0.75).abs() < 1e-9);
+
+		let none = MatchResult::default();
+		assert_eq!(0.5, none.score());
+		assert_eq!(0.0, none.elo());
+		assert_eq!((0.0, 0.0), none.elo_interval());
+		for extreme in [MatchResult { wins: 10, draws: 0, losses: 0 }, MatchResult { wins: 0, draws: 0, losses: 10 }] {
+			let (low, high) = extreme.elo_interval();
+			assert!(extreme.elo().is_finite() && low.is_finite() && high.is_finite());
+			assert!(low <= extreme.elo() && extreme.elo() <= high);
+		}
+		assert!(MatchResult { wins: 10, draws: 0, losses: 0 }.elo() > 300.0);
+	}
+
+	#[test]
+	fn engine_specs() {
+		let plain = EngineSpec::parse("weights.txt", "piece_count").unwrap();
+		assert_eq!(EngineSpec { heuristic: "piece_count".to_string(), weights: Some("weights.txt".to_string()),
+			depth: EngineSpec::DEFAULT_DEPTH, time_budget: None, book: None, solver: None }, plain);
+		let full = EngineSpec::parse("heuristic=1000*centroid(power=2) + 5*connected_components; depth=3; time=250; book=open.qbk", "piece_count").unwrap();
+		assert_eq!(EngineSpec { heuristic: "1000*centroid(power=2) + 5*connected_components".to_string(), weights: None,
+			depth: 3, time_budget: Some(Duration::from_millis(250)), book: Some("open.qbk".to_string()), solver: None }, full);
+		let solving = EngineSpec::parse("solver=plies=3, max_stragglers=2; depth=1", "piece_count").unwrap();
+		assert_eq!(Some(SolverSettings { max_stragglers: 2, plies: 3 }), solving.solver);
+		assert_eq!(Some(SolverSettings::default()), EngineSpec::parse("solver=", "piece_count").unwrap().solver);
+		assert!(EngineSpec::parse("solver=plies=many", "piece_count").is_err());
+		assert!(EngineSpec::parse("depth=0", "piece_count").is_err());
+		assert!(EngineSpec::parse("time=soon", "piece_count").is_err());
+		assert!(EngineSpec::parse("speed=3", "piece_count").is_err());
+	}
+
+	#[test]
+	fn sprt_concludes() {
+		let sprt = SprtConfig::default();
+		assert_eq!(SprtStatus::AcceptH1, MatchResult { wins: 300, draws: 100, losses: 100 }.sprt_status(&sprt));
+		assert_eq!(SprtStatus::AcceptH0, MatchResult { wins: 100, draws: 100, losses: 300 }.sprt_status(&sprt));
+		assert_eq!(SprtStatus::Continue, MatchResult { wins: 3, draws: 2, losses: 3 }.sprt_status(&sprt));
+	}
+
+	#[test]
+	fn run_swaps_colors_over_a_shared_opening() {
+		let engine = |config: &str, depth, time_budget| EngineConfig { name: config.to_string(),
+			heuristic: parse_heuristic(config).unwrap(), depth, time_budget, book: None, solver: None };
+		// A spent budget stops the second engine after its first iteration
+		let (first, second) = (engine(DEFAULT_HEURISTIC, 1, None), engine("piece_count", 3, Some(Duration::ZERO)));
+		let arena = Arena { opening_plies: 8, rules: DrawRules { max_plies: Some(60), ..DrawRules::default() },
+			max_games: 2, seed: 1, sprt: None };
+		let mut reports = vec![];
+		let result = arena.run(&first, &second, |result, opening| reports.push((*result, opening.clone())));
+		assert_eq!(2, reports.len());
+		assert_eq!(reports[0].1, reports[1].1);
+		assert_ne!(Board::start_position(9), reports[0].1);
+
+		let opening = &reports[0].1;
+		let mut rng = ChaCha12Rng::seed_from_u64(arena.seed);
+		let as_white = arena.play_game(opening, &first, &second, &mut rng);
+		let as_black = arena.play_game(opening, &second, &first, &mut rng);
+		assert_eq!((GameScore::Win, GameScore::Loss), (as_white, as_black));
+		assert_eq!(MatchResult { wins: 1, draws: 0, losses: 0 }, reports[0].0);
+		assert_eq!(MatchResult { wins: 2, draws: 0, losses: 0 }, result);
+		assert_eq!(result, reports[1].0);
+	}
+}
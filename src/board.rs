@@ -3,9 +3,10 @@ use itertools::Itertools;
 use std::cmp;
 use tinyvec::ArrayVec;
 
+use crate::components::*;
 use crate::hashes::*;
 
-const N_PIECES_PER_COLOR: usize = 20;
+pub const N_PIECES_PER_COLOR: usize = 20;
 
 #[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub struct Coord(pub i32, pub i32);
@@ -14,7 +15,7 @@ pub struct Coord(pub i32, pub i32);
 pub enum Color { Black, White }
 
 impl Color {
-	fn opponent(&self) -> Color {
+	pub fn opponent(&self) -> Color {
 		match &self {
 			Color::White => Color::Black,
 			Color::Black => Color::White
@@ -41,6 +42,13 @@ impl Move {
 		}
 	}
 
+	#[inline]
+	pub fn color(&self) -> Color {
+		match self {
+			Move::Movement { color, .. } | Move::Placement { color, .. } => *color
+		}
+	}
+
 	#[inline]
 	pub fn gap(&self) -> i32 {
 		match self {
@@ -98,9 +106,41 @@ impl MoveDelta {
 	}
 }
 
+/// A color wins once all of its pieces on the board form a single orthogonally connected
+/// group. A color with every piece in reserve has no group and cannot have won. If a move
+/// leaves both colors connected, the player who made it wins. Draws depend on the history
+/// of the game, so only `game::Game` reports them.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum GameOutcome { Ongoing, Win(Color), Draw(DrawReason) }
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum DrawReason { Repetition, NoProgress, MoveLimit }
+
+/// What a move does, for describing it to a player.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MoveEffects {
+	pub dest: Coord,
+	/// Enemy pieces sent back to their owner's reserve, including declined conversions
+	pub captured: Vec<Coord>,
+	/// Enemy pieces replaced by the mover's pieces from reserve
+	pub converted: Vec<Coord>,
+	/// Pieces that could legally have been converted but were not chosen, and so were captured
+	pub declined_conversions: Vec<Coord>,
+	pub white_reserve: i32,
+	pub black_reserve: i32,
+	pub wins: bool
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum IllegalMoveReason {
-	ActiveNotOwned, PivotNotOwned, DestNotEmpty, DestNotInBounds, GapTooBig, EmptyReserve, TriedConvertCapture
+	ActiveNotOwned, PivotNotOwned, DestNotEmpty, DestNotInBounds, GapTooBig, EmptyReserve, TriedConvertCapture,
+	NotConvertible, DuplicateConversion, InsufficientReserveForConversion, PlacementOutOfBounds, WrongColorToMove,
+	/// Fewer conversions than both the candidates and the reserve allow; every convertible piece
+	/// that is not also capturable must be converted when the reserve covers them all, and
+	/// otherwise exactly `reserve` of them
+	DeclinedConversion,
+	/// The game had already ended; only reported by `Game`
+	GameOver
 }
 
 
@@ -113,7 +153,11 @@ pub struct Board {
 	pub white_reserve: i32,
 	pub black_reserve: i32,
 	pub whose_move: Color,
-	pub zobrist_hash: u64
+	pub zobrist_hash: u64,
+	/// Kept up to date by `apply`; call `recompute_components` after editing `white` or `black` directly,
+	/// since `outcome` reads these without checking them
+	white_components: Components,
+	black_components: Components
 }
 
 impl PartialEq for Board {
@@ -129,7 +173,15 @@ impl PartialEq for Board {
 }
 
 impl Board {
+	/// Each side's reserve holds the pieces it does not have on the board.
 	pub fn from_position(board_size: i32, whose_move: Color, white: OrdSet<Coord>, black: OrdSet<Coord>) -> Board {
+		let white_reserve = (N_PIECES_PER_COLOR - white.len()).try_into().unwrap_or(0);
+		let black_reserve = (N_PIECES_PER_COLOR - black.len()).try_into().unwrap_or(0);
+		Board::from_position_with_reserves(board_size, whose_move, white, black, white_reserve, black_reserve)
+	}
+
+	pub fn from_position_with_reserves(board_size: i32, whose_move: Color, white: OrdSet<Coord>, black: OrdSet<Coord>,
+			white_reserve: i32, black_reserve: i32) -> Board {
 		let white_illegals: Vec<_> = white.iter().cloned().filter(|&Coord(x, y)| x < 0 || y < 0 || x >= board_size || y >= board_size).collect();
 		if !white_illegals.is_empty() {
 			panic!("White pieces out of bounds for {board_size:?}x{board_size:?} board: {:?}", white);
@@ -138,9 +190,27 @@ impl Board {
 		if !black_illegals.is_empty() {
 			panic!("Black pieces out of bounds for {board_size:?}x{board_size:?} board: {:?}", black);
 		}
-		let white_reserve = (N_PIECES_PER_COLOR - white.len()).try_into().unwrap_or(0);
-		let black_reserve = (N_PIECES_PER_COLOR - black.len()).try_into().unwrap_or(0);
-		Board { board_size, whose_move, white, black, white_reserve, black_reserve, max_gap: 2, zobrist_hash: 0 }
+		let white_components = Components::from_pieces(board_size, white.iter().cloned());
+		let black_components = Components::from_pieces(board_size, black.iter().cloned());
+		let mut board = Board { board_size, whose_move, white, black, white_reserve, black_reserve, max_gap: 2, zobrist_hash: 0,
+			white_components, black_components };
+		board.zobrist_hash = board.compute_zobrist_hash();
+		board
+	}
+
+	/// Hash of the position from scratch; `apply` keeps `zobrist_hash` equal to this incrementally.
+	pub fn compute_zobrist_hash(&self) -> u64 {
+		let mut hash = reserve_hash(Color::White, self.white_reserve)
+			^ reserve_hash(Color::Black, self.black_reserve)
+			^ turn_hash(self.whose_move);
+		for coord in self.white.iter() { hash ^= piece_hash(Color::White, *coord) }
+		for coord in self.black.iter() { hash ^= piece_hash(Color::Black, *coord) }
+		hash
+	}
+
+	pub fn recompute_components(&mut self) {
+		self.white_components = Components::from_pieces(self.board_size, self.white.iter().cloned());
+		self.black_components = Components::from_pieces(self.board_size, self.black.iter().cloned());
 	}
 
 	#[inline]
@@ -204,36 +274,71 @@ impl Board {
 		}
 	}
 
+	/// Checks turn order after everything else, so the most specific reason is reported for
+	/// a move that is also out of turn.
 	#[inline]
 	pub fn valid_move(&self, mov: &Move) -> Option<IllegalMoveReason> {
+		self.valid_move_ignoring_turn(mov).or_else(||
+			(mov.color() != self.whose_move).then_some(IllegalMoveReason::WrongColorToMove))
+	}
+
+	/// Ownership, destination and gap checks of a movement, which do not depend on its conversions.
+	fn movement_shape_error(&self, mov: &Move) -> Option<IllegalMoveReason> {
+		let Move::Movement { color, active, pivot, .. } = mov else { return None };
+		let pieces = self.pieces_of(*color);
+		if !pieces.contains(active) {
+			return Some(IllegalMoveReason::ActiveNotOwned);
+		}
+		if !pieces.contains(pivot) {
+			return Some(IllegalMoveReason::PivotNotOwned);
+		}
+		let dest = mov.dest();
+		if self.is_occupied(dest) {
+			return Some(IllegalMoveReason::DestNotEmpty);
+		}
+		if !self.in_bounds(dest) {
+			return Some(IllegalMoveReason::DestNotInBounds);
+		}
+		if mov.gap() > self.max_gap {
+			return Some(IllegalMoveReason::GapTooBig);
+		}
+		None
+	}
+
+	/// Whether `mov` would be legal if it were its color's turn.
+	#[inline]
+	pub fn valid_move_ignoring_turn(&self, mov: &Move) -> Option<IllegalMoveReason> {
 		match mov {
-			Move::Movement { color, active, pivot, conversions } => {
-				let pieces = if *color == Color::Black { &self.black } else { &self.white };
-				if !pieces.contains(active) {
-					return Some(IllegalMoveReason::ActiveNotOwned);
-				}
-				if !pieces.contains(pivot) {
-					return Some(IllegalMoveReason::PivotNotOwned);
+			Move::Movement { color, active, conversions, .. } => {
+				if let Some(reason) = self.movement_shape_error(mov) {
+					return Some(reason);
 				}
 				let dest = mov.dest();
-				if self.is_occupied(dest) {
-					return Some(IllegalMoveReason::DestNotEmpty);
+				let capturable: Vec<_> = self.capturable_around(*color, *active, dest).collect();
+				if conversions.iter().any(|converted| capturable.contains(converted)) {
+					return Some(IllegalMoveReason::TriedConvertCapture)
 				}
-				if !self.in_bounds(dest) {
-					return Some(IllegalMoveReason::DestNotInBounds);
+				let choosable: Vec<_> = self.convertible_around(*color, *active, dest)
+					.filter(|coord| !capturable.contains(coord))
+					.collect();
+				if conversions.iter().any(|converted| !choosable.contains(converted)) {
+					return Some(IllegalMoveReason::NotConvertible);
 				}
-				if mov.gap() > self.max_gap {
-					return Some(IllegalMoveReason::GapTooBig);
+				if conversions.iter().duplicates().next().is_some() {
+					return Some(IllegalMoveReason::DuplicateConversion);
 				}
-				if conversions.iter().cloned()
-					.any(|converted| self.capturable_around(*color, *active, dest)
-						.contains(&converted)) {
-					return Some(IllegalMoveReason::TriedConvertCapture)
+				if conversions.len() as i32 > self.reserve_of(*color) {
+					return Some(IllegalMoveReason::InsufficientReserveForConversion);
+				}
+				if (conversions.len() as i32) < cmp::min(choosable.len() as i32, self.reserve_of(*color)) {
+					return Some(IllegalMoveReason::DeclinedConversion);
 				}
 				None
 			},
 			Move::Placement { color, at } => {
-				if self.is_occupied(*at) {
+				if !self.in_bounds(*at) {
+					Some(IllegalMoveReason::PlacementOutOfBounds)
+				} else if self.is_occupied(*at) {
 					Some(IllegalMoveReason::DestNotEmpty)
 				} else if self.reserve_of(*color) <= 0 {
 					Some(IllegalMoveReason::EmptyReserve)
@@ -332,17 +437,38 @@ impl Board {
 		visited
 	}
 
+	#[inline]
+	pub fn components_of(&self, color: Color) -> &Components {
+		match color {
+			Color::White => &self.white_components,
+			Color::Black => &self.black_components
+		}
+	}
+
+	#[inline]
+	pub fn component_count(&self, color: Color) -> usize {
+		self.components_of(color).count()
+	}
+
 	#[inline]
 	pub fn color_connected(&self, color: Color) -> bool {
-		let source: Coord = *self.pieces_of(color).iter().next().unwrap();
-		self.flood_fill(color, source).len() == self.pieces_of(color).len()
+		self.component_count(color) == 1
+	}
+
+	#[inline]
+	pub fn outcome(&self) -> GameOutcome {
+		let mover = self.whose_move.opponent();
+		if self.color_connected(mover) { GameOutcome::Win(mover) }
+		else if self.color_connected(mover.opponent()) { GameOutcome::Win(mover.opponent()) }
+		else { GameOutcome::Ongoing }
 	}
 
 	#[inline]
 	pub fn winner(&self) -> Option<Color> {
-		if self.color_connected(Color::Black) { Some(Color::Black) }
-		else if self.color_connected(Color::White) { Some(Color::White) }
-		else { None }
+		match self.outcome() {
+			GameOutcome::Win(color) => Some(color),
+			GameOutcome::Ongoing | GameOutcome::Draw(_) => None
+		}
 	}
 
 	#[inline]
@@ -369,28 +495,40 @@ impl Board {
 		})
 	}
 
+	/// Legal movements of `color` from `active` over `pivot`, one per allowed set of conversions:
+	/// every convertible piece that is not also capturable if the reserve covers them, otherwise
+	/// each set of exactly `reserve` of them.
+	fn movements_over(&self, color: Color, active: Coord, pivot: Coord) -> Vec<Move> {
+		let mut mvmts: Vec<Move> = vec![];
+		if self.movement_shape_error(&Move::movement(color, active, pivot)).is_none() {
+			if self.reserve_of(color) < 0 {
+				panic!("Negative reserve for {:?}: {} on board with {} in reserve",
+					color, self.pieces_of(color).len(), self.reserve_of(color));
+			}
+			let base_mvmt = Move::movement(color, active, pivot);
+			let capturable: Vec<_> = self.capturable_around(color, active, base_mvmt.dest()).collect();
+			let conversions: Vec<_> = self.convertible_around(color, active, base_mvmt.dest())
+				.filter(|coord| !capturable.contains(coord))
+				.collect();
+			if conversions.len() as i32 <= self.reserve_of(color) {
+				mvmts.push(Move::Movement { color, active, pivot, conversions });
+			} else {
+				for combination in conversions.into_iter().combinations(self.reserve_of(color) as usize) {
+					mvmts.push(Move::Movement { color, active, pivot, conversions: combination });
+				}
+			}
+		}
+		mvmts.retain(|x| self.valid_move_ignoring_turn(x).is_none());
+		mvmts
+	}
+
 	#[inline]
 	pub fn moves_of(&self, color: Color) -> impl Iterator<Item=Move> + '_ {
 		let mut mvmts: Vec<Move> = vec![];
 
 		for active in self.pieces_of(color).iter().cloned() {
 			for pivot in self.pieces_of(color).iter().cloned() {
-				if self.valid_move(&Move::movement(color, active, pivot)).is_none() {
-					if self.reserve_of(color) < 0 {
-						panic!("Negative reserve for {:?}: {} on board with {} in reserve",
-							color, self.pieces_of(color).len(), self.reserve_of(color));
-					}
-					let base_mvmt = Move::movement(color, active, pivot);
-					let conversions: Vec<_> = self.convertible_around(color, active, base_mvmt.dest())
-						.collect();
-					if conversions.len() as i32 <= self.reserve_of(color) {
-						mvmts.push(Move::Movement { color, active, pivot, conversions });
-					} else {
-						for combination in conversions.into_iter().combinations(self.reserve_of(color) as usize) {
-								mvmts.push(Move::Movement { color, active, pivot, conversions: combination });
-						}
-					}
-				}
+				mvmts.extend(self.movements_over(color, active, pivot));
 			}
 		}
 
@@ -399,8 +537,45 @@ impl Board {
 			.filter(move |coord| self.reserve_of(color) > 0 && !self.is_occupied(*coord))
 			.map(move |coord| Move::Placement { color, at: coord });
 
-		mvmts.into_iter().filter(|x| self.valid_move(x).is_none())
-			.chain(placements)
+		mvmts.into_iter().chain(placements)
+	}
+
+	/// Squares within pivoting range of `center`, nearest first.
+	#[inline]
+	fn pivot_range(&self, center: Coord) -> impl Iterator<Item=Coord> + '_ {
+		let reach = self.max_gap + 1;
+		(1..=reach).flat_map(move |distance| (-distance..=distance)
+			.flat_map(move |dx| (-distance..=distance).map(move |dy| (dx, dy)))
+			.filter(move |(dx, dy)| cmp::max(dx.abs(), dy.abs()) == distance)
+			.map(move |(dx, dy)| Coord(center.0 + dx, center.1 + dy)))
+			.filter(|coord| self.in_bounds(*coord))
+	}
+
+	/// Pieces the side to move could pivot `active` over.
+	pub fn pivots_for(&self, active: Coord) -> impl Iterator<Item=Coord> + '_ {
+		let color = self.whose_move;
+		self.pivot_range(active)
+			.filter(move |pivot| !self.movements_over(color, active, *pivot).is_empty())
+	}
+
+	/// Legal movements of the piece on `active`, if it belongs to the side to move.
+	pub fn moves_from(&self, active: Coord) -> impl Iterator<Item=Move> + '_ {
+		let color = self.whose_move;
+		self.pieces_of(color).contains(&active).then(|| self.pivot_range(active))
+			.into_iter()
+			.flatten()
+			.flat_map(move |pivot| self.movements_over(color, active, pivot))
+	}
+
+	/// Legal moves of the side to move that end on `dest`, including placements.
+	pub fn moves_to(&self, dest: Coord) -> impl Iterator<Item=Move> + '_ {
+		let color = self.whose_move;
+		let placement = (self.in_bounds(dest) && !self.is_occupied(dest) && self.reserve_of(color) > 0)
+			.then_some(Move::Placement { color, at: dest });
+		self.pivot_range(dest)
+			.filter(move |pivot| self.pieces_of(color).contains(pivot))
+			.flat_map(move |pivot| self.movements_over(color, Coord(2*pivot.0 - dest.0, 2*pivot.1 - dest.1), pivot))
+			.chain(placement)
 	}
 
 	#[inline]
@@ -441,6 +616,40 @@ impl Board {
 		this_move_delta
 	}
 
+	/// What playing `mov` would do, or why it cannot be played here.
+	pub fn explain_move(&self, mov: &Move) -> Result<MoveEffects, IllegalMoveReason> {
+		if let Some(reason) = self.valid_move(mov) {
+			return Err(reason);
+		}
+		let color = mov.color();
+		let dest = mov.dest();
+		let delta = self.move_delta(mov);
+		let (opponent_minus, own_plus) = match color {
+			Color::White => (&delta.black_minus, &delta.white_plus),
+			Color::Black => (&delta.white_minus, &delta.black_plus)
+		};
+		let converted: Vec<_> = opponent_minus.iter().cloned().filter(|coord| own_plus.contains(coord)).collect();
+		let captured: Vec<_> = opponent_minus.iter().cloned().filter(|coord| !converted.contains(coord)).collect();
+		let declined_conversions = match mov {
+			Move::Movement { active, .. } => {
+				let capturable: Vec<_> = self.capturable_around(color, *active, dest).collect();
+				self.convertible_around(color, *active, dest)
+					.filter(|coord| !converted.contains(coord) && !capturable.contains(coord))
+					.collect()
+			},
+			Move::Placement { .. } => vec![]
+		};
+		Ok(MoveEffects {
+			dest,
+			captured,
+			converted,
+			declined_conversions,
+			white_reserve: self.white_reserve + delta.white_reserve,
+			black_reserve: self.black_reserve + delta.black_reserve,
+			wins: self.apply(mov).winner() == Some(color)
+		})
+	}
+
 	#[inline]
 	pub fn apply_to_zobrist_hash(&self, delta: &MoveDelta) -> u64 {
 		let mut new_hash = self.zobrist_hash;
@@ -456,16 +665,36 @@ impl Board {
 		new_hash
 	}
 
+	/// Checked `apply` for moves from untrusted input.
+	pub fn try_apply(&self, mov: &Move) -> Result<Board, IllegalMoveReason> {
+		match self.valid_move(mov) {
+			Some(reason) => Err(reason),
+			None => Ok(self.apply(mov))
+		}
+	}
+
 	#[inline]
 	pub fn apply(&self, mov: &Move) -> Board {
 		debug_assert_eq!(None, self.valid_move(mov));
 		let mut new_board = self.clone();
 		let delta = self.move_delta(mov);
 		new_board.zobrist_hash = self.apply_to_zobrist_hash(&delta);
-		for coord in delta.white_minus { new_board.white.remove(&coord); }
-		for coord in delta.white_plus { new_board.white.insert(coord); }
-		for coord in delta.black_minus { new_board.black.remove(&coord); }
-		for coord in delta.black_plus { new_board.black.insert(coord); }
+		for coord in delta.white_minus {
+			new_board.white.remove(&coord);
+			new_board.white_components.remove(coord);
+		}
+		for coord in delta.white_plus {
+			new_board.white.insert(coord);
+			new_board.white_components.insert(coord);
+		}
+		for coord in delta.black_minus {
+			new_board.black.remove(&coord);
+			new_board.black_components.remove(coord);
+		}
+		for coord in delta.black_plus {
+			new_board.black.insert(coord);
+			new_board.black_components.insert(coord);
+		}
 		new_board.white_reserve += delta.white_reserve;
 		new_board.black_reserve += delta.black_reserve;
 
@@ -475,8 +704,32 @@ impl Board {
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
 	use super::*;
+	use crate::symmetry::*;
+	use rand_chacha::rand_core::{RngCore, SeedableRng};
+	use rand_chacha::ChaCha12Rng;
+
+	/// Every position of `games` random games from the start position, each up to `plies`
+	/// plies long and ending early at a win, for tests that check properties of many boards.
+	pub fn random_positions(seed: u64, games: usize, plies: usize) -> Vec<Board> {
+		let mut rng = ChaCha12Rng::seed_from_u64(seed);
+		let mut positions = vec![];
+		for _ in 0..games {
+			let mut board = Board::start_position(9);
+			for _ in 0..plies {
+				let moves: Vec<_> = board.moves().collect();
+				if moves.is_empty() || board.winner().is_some() {
+					break;
+				}
+				let next = board.apply(&moves[rng.next_u64() as usize % moves.len()]);
+				positions.push(board);
+				board = next;
+			}
+			positions.push(board);
+		}
+		positions
+	}
 
 	#[test]
 	fn test_valid_move() {
@@ -505,9 +758,7 @@ mod tests {
 			board.valid_move(&Move::movement(Color::White, Coord(1,1), Coord(1,0)))
 			.expect("DestNotInBounds not illegal"));
 
-		fn hvflip(coord: Coord) -> Coord {
-			Coord(BOARD_SIZE - coord.0 - 1, BOARD_SIZE - coord.1 - 1)
-		}
+		let hvflip = |coord| Symmetry::Rotate180.apply(coord, BOARD_SIZE);
 		assert_eq!(IllegalMoveReason::DestNotEmpty,
 			board.valid_move(&Move::movement(Color::White, hvflip(Coord(0,0)), hvflip(Coord(3,0))))
 			.expect("DestNotEmpty not illegal"));
@@ -524,9 +775,7 @@ mod tests {
 			board.valid_move(&Move::movement(Color::White, hvflip(Coord(1,1)), hvflip(Coord(1,0))))
 			.expect("DestNotInBounds not illegal"));
 
-		fn hflip(coord: Coord) -> Coord {
-			Coord(BOARD_SIZE - coord.0 - 1, coord.1)
-		}
+		let hflip = |coord| Symmetry::FlipX.apply(coord, BOARD_SIZE);
 		assert_eq!(IllegalMoveReason::DestNotEmpty,
 			board.valid_move(&Move::movement(Color::Black, hflip(Coord(0,0)), hflip(Coord(3,0))))
 			.expect("DestNotEmpty not illegal"));
@@ -543,9 +792,7 @@ mod tests {
 			board.valid_move(&Move::movement(Color::Black, hflip(Coord(1,1)), hflip(Coord(1,0))))
 			.expect("DestNotInBounds not illegal"));
 
-		fn vflip(coord: Coord) -> Coord {
-			Coord(coord.0, BOARD_SIZE - coord.1 - 1)
-		}
+		let vflip = |coord| Symmetry::FlipY.apply(coord, BOARD_SIZE);
 		assert_eq!(IllegalMoveReason::DestNotEmpty,
 			board.valid_move(&Move::movement(Color::Black, vflip(Coord(0,0)), vflip(Coord(3,0))))
 			.expect("DestNotEmpty not illegal"));
@@ -573,6 +820,173 @@ mod tests {
 		assert_eq!(OrdSet::<Coord>::from(expected), OrdSet::<Coord>::from(actual));
 	}
 
+	#[test]
+	fn test_conversion_validation() {
+		let black = OrdSet::from(vec![Coord(3,3), Coord(5,3), Coord(7,5), Coord(6,5)]);
+		let white = OrdSet::from(vec![Coord(4,4), Coord(5,4), Coord(4,5)]);
+		let mut board = Board::from_position(9, Color::Black, white, black);
+		let converting = |conversions: Vec<Coord>| Move::Movement {
+			color: Color::Black, active: Coord(7,5), pivot: Coord(6,5), conversions };
+
+		assert_eq!(None, board.valid_move(&converting(vec![Coord(4,4), Coord(5,4)])));
+		assert_eq!(Some(IllegalMoveReason::NotConvertible),
+			board.valid_move(&converting(vec![Coord(4,5)])));
+		assert_eq!(Some(IllegalMoveReason::NotConvertible),
+			board.valid_move(&converting(vec![Coord(4,4), Coord(6,6)])));
+		assert_eq!(Some(IllegalMoveReason::DuplicateConversion),
+			board.valid_move(&converting(vec![Coord(4,4), Coord(4,4)])));
+		assert_eq!(Some(IllegalMoveReason::DeclinedConversion), board.valid_move(&converting(vec![Coord(5,4)])));
+		assert_eq!(Some(IllegalMoveReason::DeclinedConversion), board.valid_move(&converting(vec![])));
+		board.black_reserve = 1;
+		assert_eq!(None, board.valid_move(&converting(vec![Coord(5,4)])));
+		assert_eq!(Some(IllegalMoveReason::DeclinedConversion), board.valid_move(&converting(vec![])));
+		assert_eq!(Some(IllegalMoveReason::InsufficientReserveForConversion),
+			board.valid_move(&converting(vec![Coord(4,4), Coord(5,4)])));
+		board.black_reserve = 0;
+		assert_eq!(None, board.valid_move(&converting(vec![])));
+	}
+
+	#[test]
+	fn test_validation_matches_generation() {
+		let sorted = |mov: Move| match mov {
+			Move::Movement { color, active, pivot, mut conversions } => {
+				conversions.sort();
+				Move::Movement { color, active, pivot, conversions }
+			},
+			placement => placement
+		};
+		for board in random_positions(9, 3, 40) {
+			let color = board.whose_move;
+			let generated: OrdSet<Move> = board.moves().map(sorted).collect();
+			let mut candidates = vec![];
+			for active in board.pieces_of(color).iter().cloned() {
+				for pivot in board.pieces_of(color).iter().cloned() {
+					let dest = Move::movement(color, active, pivot).dest();
+					let mut nearby: Vec<_> = board.convertible_around(color, active, dest).collect();
+					let others: Vec<_> = board.neighborhood(dest).filter(|coord| board.is_occupied(*coord) && !nearby.contains(coord)).take(2).collect();
+					nearby.extend(others);
+					for size in 0..=cmp::min(nearby.len(), 3) {
+						for conversions in nearby.iter().cloned().combinations(size) {
+							candidates.push(Move::Movement { color, active, pivot, conversions });
+						}
+					}
+					if let Some(first) = nearby.first() {
+						candidates.push(Move::Movement { color, active, pivot, conversions: vec![*first, *first] });
+					}
+				}
+			}
+			candidates.extend((-1..=board.board_size).flat_map(|x| (-1..=board.board_size).map(move |y| Move::Placement { color, at: Coord(x, y) })));
+			for mov in candidates {
+				let mov = sorted(mov);
+				assert_eq!(generated.contains(&mov), board.valid_move(&mov).is_none(), "{mov:?}");
+			}
+			assert!(generated.iter().all(|mov| board.valid_move(mov).is_none()));
+		}
+	}
+
+	#[test]
+	fn test_placement_and_turn_validation() {
+		let board = Board::start_position(9);
+		let mut with_reserve = board.clone();
+		with_reserve.white_reserve = 1;
+		with_reserve.black_reserve = 1;
+
+		assert_eq!(Some(IllegalMoveReason::PlacementOutOfBounds),
+			with_reserve.valid_move(&Move::Placement { color: Color::White, at: Coord(20,-3) }));
+		assert_eq!(Some(IllegalMoveReason::PlacementOutOfBounds),
+			with_reserve.valid_move(&Move::Placement { color: Color::White, at: Coord(9,4) }));
+		assert_eq!(None, with_reserve.valid_move(&Move::Placement { color: Color::White, at: Coord(4,4) }));
+		assert_eq!(Some(IllegalMoveReason::WrongColorToMove),
+			with_reserve.valid_move(&Move::Placement { color: Color::Black, at: Coord(4,4) }));
+
+		let black_move = Move::movement(Color::Black, Coord(8,0), Coord(7,1));
+		assert_eq!(Some(IllegalMoveReason::WrongColorToMove), board.valid_move(&black_move));
+		assert_eq!(None, board.valid_move_ignoring_turn(&black_move));
+	}
+
+	#[test]
+	fn test_try_apply() {
+		let mut board = Board::start_position(9);
+		board.white_reserve = 1;
+		assert_eq!(Err(IllegalMoveReason::PlacementOutOfBounds),
+			board.try_apply(&Move::Placement { color: Color::White, at: Coord(20,-3) }));
+		assert_eq!(Err(IllegalMoveReason::WrongColorToMove),
+			board.try_apply(&Move::movement(Color::Black, Coord(8,0), Coord(7,1))));
+
+		let white_move = Move::movement(Color::White, Coord(0,0), Coord(1,1));
+		let after = board.try_apply(&white_move).unwrap();
+		assert_eq!(board.apply(&white_move), after);
+		assert_eq!(Color::Black, after.whose_move);
+		assert_eq!(Ok(after.apply(&Move::movement(Color::Black, Coord(8,0), Coord(7,1)))),
+			after.try_apply(&Move::movement(Color::Black, Coord(8,0), Coord(7,1))));
+	}
+
+	#[test]
+	fn test_explain_move() {
+		let black = OrdSet::from(vec![Coord(3,3), Coord(5,3), Coord(7,5), Coord(6,5)]);
+		let white = OrdSet::from(vec![Coord(4,4), Coord(5,4), Coord(4,5)]);
+		let mut board = Board::from_position(9, Color::Black, white, black);
+		board.black_reserve = 1;
+		let mov = Move::Movement { color: Color::Black, active: Coord(7,5), pivot: Coord(6,5), conversions: vec![Coord(5,4)] };
+		let effects = board.explain_move(&mov).unwrap();
+		assert_eq!(Coord(5,5), effects.dest);
+		assert_eq!(vec![Coord(5,4)], effects.converted);
+		assert_eq!(vec![Coord(4,4)], effects.declined_conversions);
+		assert_eq!(vec![Coord(4,4)], effects.captured);
+		assert_eq!(0, effects.black_reserve);
+		assert_eq!(board.white_reserve + 2, effects.white_reserve);
+		assert!(!effects.wins);
+
+		let start = Board::start_position(9);
+		let quiet = start.explain_move(&Move::movement(Color::White, Coord(0,0), Coord(1,1))).unwrap();
+		assert_eq!(Coord(2,2), quiet.dest);
+		assert!(quiet.captured.is_empty() && quiet.converted.is_empty() && quiet.declined_conversions.is_empty());
+		assert_eq!((0, 0), (quiet.white_reserve, quiet.black_reserve));
+
+		let white = OrdSet::from(vec![Coord(0,4), Coord(0,3), Coord(0,0), Coord(1,0), Coord(1,1), Coord(1,2)]);
+		let black = OrdSet::from(vec![Coord(0,1), Coord(8,8)]);
+		let board = Board::from_position(9, Color::White, white, black);
+		let capture = board.explain_move(&Move::movement(Color::White, Coord(0,4), Coord(0,3))).unwrap();
+		assert_eq!(vec![Coord(0,1)], capture.captured);
+		assert!(capture.declined_conversions.is_empty());
+		let convert = Move::Movement { color: Color::White, active: Coord(0,4), pivot: Coord(0,3), conversions: vec![Coord(0,1)] };
+		assert_eq!(Some(IllegalMoveReason::TriedConvertCapture), board.valid_move(&convert));
+		assert_eq!(Err(IllegalMoveReason::TriedConvertCapture), board.explain_move(&convert));
+		assert_eq!(Err(IllegalMoveReason::PlacementOutOfBounds),
+			board.explain_move(&Move::Placement { color: Color::White, at: Coord(20,-3) }));
+		assert_eq!(Err(IllegalMoveReason::WrongColorToMove),
+			board.explain_move(&Move::Placement { color: Color::Black, at: Coord(5,5) }));
+	}
+
+	#[test]
+	fn test_filtered_move_generation() {
+		for board in random_positions(2, 1, 30) {
+			let all_moves: Vec<_> = board.moves().collect();
+			for coord in board.all_coords() {
+				let mut expected_from: Vec<_> = all_moves.iter()
+					.filter(|mov| matches!(mov, Move::Movement { active, .. } if *active == coord))
+					.cloned()
+					.collect();
+				let mut expected_to: Vec<_> = all_moves.iter().filter(|mov| mov.dest() == coord).cloned().collect();
+				let mut expected_pivots: Vec<_> = expected_from.iter()
+					.map(|mov| match mov { Move::Movement { pivot, .. } => *pivot, _ => unreachable!() })
+					.collect();
+				let mut from: Vec<_> = board.moves_from(coord).collect();
+				let mut to: Vec<_> = board.moves_to(coord).collect();
+				let mut pivots: Vec<_> = board.pivots_for(coord).collect();
+				for list in [&mut expected_from, &mut from, &mut expected_to, &mut to] {
+					list.sort();
+				}
+				expected_pivots.sort();
+				expected_pivots.dedup();
+				pivots.sort();
+				assert_eq!(expected_from, from);
+				assert_eq!(expected_to, to);
+				assert_eq!(expected_pivots, pivots);
+			}
+		}
+	}
+
 	#[test]
 	fn test_capturables() {
 		let black = OrdSet::<Coord>::from(
@@ -603,6 +1017,30 @@ mod tests {
 		assert!(board.color_connected(Color::White));
 	}
 
+	#[test]
+	fn test_simultaneous_connection_goes_to_mover() {
+		let white = OrdSet::from(vec![Coord(0,0), Coord(0,1)]);
+		let black = OrdSet::from(vec![Coord(5,5), Coord(5,6)]);
+		let after_white_move = Board::from_position(9, Color::Black, white.clone(), black.clone());
+		assert_eq!(GameOutcome::Win(Color::White), after_white_move.outcome());
+		let after_black_move = Board::from_position(9, Color::White, white, black);
+		assert_eq!(GameOutcome::Win(Color::Black), after_black_move.outcome());
+	}
+
+	#[test]
+	fn test_color_with_no_pieces_on_board() {
+		let scattered = OrdSet::from(vec![Coord(0,0), Coord(2,2)]);
+		let board = Board::from_position(9, Color::White, scattered, OrdSet::new());
+		assert_eq!(20, board.black_reserve);
+		assert!(!board.color_connected(Color::Black));
+		assert_eq!(GameOutcome::Ongoing, board.outcome());
+
+		let connected = OrdSet::from(vec![Coord(0,0), Coord(0,1)]);
+		let board = Board::from_position(9, Color::White, connected, OrdSet::new());
+		assert_eq!(GameOutcome::Win(Color::White), board.outcome());
+		assert_eq!(Some(Color::White), board.winner());
+	}
+
 	#[test]
 	fn test_available_moves_from_start() {
 		let board = Board::start_position(9);
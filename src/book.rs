@@ -0,0 +1,257 @@
+use crate::board::*;
+use crate::game::*;
+use crate::notation::*;
+
+use rand_chacha::rand_core::RngCore;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::iter;
+use std::path::Path;
+
+/// Version 2 keys positions by canonical hash; version 1 books used plain Zobrist hashes.
+const BOOK_MAGIC: &[u8; 4] = b"QBK\x02";
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BookEntry {
+	pub mov: Move,
+	pub weight: u32,
+	pub wins: u32,
+	pub draws: u32,
+	pub losses: u32
+}
+
+/// Moves seen from each position, keyed by canonical hash so that symmetric positions share
+/// entries. Moves are stored as played on the canonical board. Statistics are from the point
+/// of view of the side making the move.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct OpeningBook {
+	pub positions: HashMap<u64, Vec<BookEntry>>
+}
+
+impl OpeningBook {
+	pub fn new() -> OpeningBook {
+		OpeningBook { positions: HashMap::new() }
+	}
+
+	/// Records the first `max_plies` moves of every game, starting from the start position.
+	/// Games that `Game::from_record` rejects are left out.
+	pub fn from_records(records: &[GameRecord], max_plies: usize) -> OpeningBook {
+		let mut book = OpeningBook::new();
+		for record in records {
+			let Ok(game) = Game::from_record(record, DrawRules::default()) else { continue };
+			let before = iter::once(game.initial()).chain(game.iter().map(|(_, board)| board));
+			for (board, mov) in before.zip(game.moves()).take(max_plies) {
+				book.add(board, mov, record.winner);
+			}
+		}
+		book
+	}
+
+	pub fn add(&mut self, board: &Board, mov: &Move, winner: Option<Color>) {
+		let symmetry = board.canonical_symmetry();
+		let mov = mov.transform(symmetry, board.board_size);
+		let entries = self.positions.entry(board.zobrist_hash_under(symmetry)).or_default();
+		let index = match entries.iter().position(|entry| entry.mov == mov) {
+			Some(index) => index,
+			None => {
+				entries.push(BookEntry { mov, weight: 0, wins: 0, draws: 0, losses: 0 });
+				entries.len() - 1
+			}
+		};
+		let entry = &mut entries[index];
+		entry.weight += 1;
+		match winner {
+			Some(color) if color == board.whose_move => entry.wins += 1,
+			Some(_) => entry.losses += 1,
+			None => entry.draws += 1
+		}
+	}
+
+	/// Book moves for this board, translated back from the canonical board. Only legal moves
+	/// are returned, which guards against hash collisions.
+	pub fn entries(&self, board: &Board) -> Vec<BookEntry> {
+		let symmetry = board.canonical_symmetry();
+		self.positions.get(&board.zobrist_hash_under(symmetry))
+			.into_iter()
+			.flatten()
+			.map(|entry| BookEntry { mov: entry.mov.transform(symmetry.inverse(), board.board_size), ..entry.clone() })
+			.filter(|entry| entry.mov.color() == board.whose_move && board.valid_move(&entry.mov).is_none())
+			.collect()
+	}
+
+	/// Picks a book move at random with probability proportional to its weight.
+	pub fn choose<R: RngCore>(&self, board: &Board, rng: &mut R) -> Option<Move> {
+		let entries: Vec<_> = self.entries(board).into_iter().filter(|entry| entry.weight > 0).collect();
+		let total: u64 = entries.iter().map(|entry| entry.weight as u64).sum();
+		if total == 0 {
+			return None;
+		}
+		let mut pick = rng.next_u64() % total;
+		for entry in entries {
+			if pick < entry.weight as u64 {
+				return Some(entry.mov.clone());
+			}
+			pick -= entry.weight as u64;
+		}
+		unreachable!()
+	}
+
+	pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+		writer.write_all(BOOK_MAGIC)?;
+		let n_entries: usize = self.positions.values().map(Vec::len).sum();
+		writer.write_all(&(n_entries as u32).to_le_bytes())?;
+		let mut hashes: Vec<_> = self.positions.keys().cloned().collect();
+		hashes.sort();
+		for hash in hashes {
+			for entry in &self.positions[&hash] {
+				writer.write_all(&hash.to_le_bytes())?;
+				for count in [entry.weight, entry.wins, entry.draws, entry.losses] {
+					writer.write_all(&count.to_le_bytes())?;
+				}
+				write_move(writer, &entry.mov)?;
+			}
+		}
+		Ok(())
+	}
+
+	pub fn read_from<R: Read>(reader: &mut R) -> io::Result<OpeningBook> {
+		let mut magic = [0; 4];
+		reader.read_exact(&mut magic)?;
+		if &magic != BOOK_MAGIC {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Not an opening book file"));
+		}
+		let mut book = OpeningBook::new();
+		let n_entries = read_u32(reader)?;
+		for _ in 0..n_entries {
+			let hash = u64::from_le_bytes(read_bytes(reader)?);
+			let weight = read_u32(reader)?;
+			let wins = read_u32(reader)?;
+			let draws = read_u32(reader)?;
+			let losses = read_u32(reader)?;
+			let mov = read_move(reader)?;
+			book.positions.entry(hash).or_default().push(BookEntry { mov, weight, wins, draws, losses });
+		}
+		Ok(book)
+	}
+
+	pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let mut bytes = vec![];
+		self.write_to(&mut bytes)?;
+		fs::write(path, bytes)
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> io::Result<OpeningBook> {
+		OpeningBook::read_from(&mut fs::File::open(path)?)
+	}
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+	let mut bytes = [0; N];
+	reader.read_exact(&mut bytes)?;
+	Ok(bytes)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+	Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn write_coord<W: Write>(writer: &mut W, coord: Coord) -> io::Result<()> {
+	writer.write_all(&[coord.0 as i8 as u8, coord.1 as i8 as u8])
+}
+
+fn read_coord<R: Read>(reader: &mut R) -> io::Result<Coord> {
+	let [x, y] = read_bytes(reader)?;
+	Ok(Coord(x as i8 as i32, y as i8 as i32))
+}
+
+fn write_move<W: Write>(writer: &mut W, mov: &Move) -> io::Result<()> {
+	let color_byte = |color: &Color| match color { Color::Black => 0, Color::White => 1 };
+	match mov {
+		Move::Placement { color, at } => {
+			writer.write_all(&[0, color_byte(color)])?;
+			write_coord(writer, *at)
+		},
+		Move::Movement { color, active, pivot, conversions } => {
+			writer.write_all(&[1, color_byte(color)])?;
+			write_coord(writer, *active)?;
+			write_coord(writer, *pivot)?;
+			writer.write_all(&[conversions.len() as u8])?;
+			for converted in conversions {
+				write_coord(writer, *converted)?;
+			}
+			Ok(())
+		}
+	}
+}
+
+fn read_move<R: Read>(reader: &mut R) -> io::Result<Move> {
+	let [kind, color] = read_bytes(reader)?;
+	let color = match color {
+		0 => Color::Black,
+		1 => Color::White,
+		_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad color in opening book"))
+	};
+	match kind {
+		0 => Ok(Move::Placement { color, at: read_coord(reader)? }),
+		1 => {
+			let active = read_coord(reader)?;
+			let pivot = read_coord(reader)?;
+			let [n_conversions] = read_bytes(reader)?;
+			let conversions = (0..n_conversions).map(|_| read_coord(reader)).collect::<io::Result<_>>()?;
+			Ok(Move::Movement { color, active, pivot, conversions })
+		},
+		_ => Err(io::Error::new(io::ErrorKind::InvalidData, "Bad move kind in opening book"))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand_chacha::rand_core::SeedableRng;
+	use rand_chacha::ChaCha12Rng;
+
+	fn example_records() -> Vec<GameRecord> {
+		let corpus = concat!(
+			"1. 1一5三 1九3五\n",
+			"2. 1二3四 2九2五\n",
+			"0-1\n",
+			"1. 1一5三 1九3五\n",
+			"1-0\n",
+			"1. 1二3四 1九3五\n",
+			"1-0\n"
+		);
+		parse_games(corpus).unwrap().1
+	}
+
+	#[test]
+	fn book_statistics() {
+		let book = OpeningBook::from_records(&example_records(), 8);
+		let start = Board::start_position(9);
+		let mut entries = book.entries(&start);
+		entries.sort_by_key(|entry| entry.weight);
+		assert_eq!(2, entries.len());
+		assert_eq!((1, 1, 0, 0), (entries[0].weight, entries[0].wins, entries[0].draws, entries[0].losses));
+		assert_eq!((2, 1, 0, 1), (entries[1].weight, entries[1].wins, entries[1].draws, entries[1].losses));
+
+		let mut rng = ChaCha12Rng::seed_from_u64(0);
+		let chosen = book.choose(&start, &mut rng).unwrap();
+		assert!(entries.iter().any(|entry| entry.mov == chosen));
+		assert_eq!(None, OpeningBook::new().choose(&start, &mut rng));
+
+		let illegal = parse_games("1. 1一5三 1一5三\n0-1\n").unwrap().1;
+		assert_eq!(OpeningBook::new(), OpeningBook::from_records(&illegal, 8));
+	}
+
+	#[test]
+	fn book_round_trip() {
+		let book = OpeningBook::from_records(&example_records(), 8);
+		let mut bytes = vec![];
+		book.write_to(&mut bytes).unwrap();
+		let loaded = OpeningBook::read_from(&mut bytes.as_slice()).unwrap();
+		assert_eq!(book, loaded);
+		assert!(OpeningBook::read_from(&mut &bytes[1..]).is_err());
+		bytes[3] = 1;
+		assert!(OpeningBook::read_from(&mut bytes.as_slice()).is_err());
+	}
+}
@@ -0,0 +1,225 @@
+use crate::board::Coord;
+
+/// Orthogonally connected groups of one color's pieces. Each occupied cell carries the id of
+/// its group, so membership, sizes and the group count are available without a flood fill.
+/// Additions merge groups directly; removals only relabel the group they were taken from.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Components {
+	board_size: i32,
+	labels: Vec<u8>,
+	sizes: Vec<u8>,
+	count: usize
+}
+
+const NO_COMPONENT: u8 = u8::MAX;
+
+impl Components {
+	pub fn new(board_size: i32) -> Components {
+		Components { board_size, labels: vec![NO_COMPONENT; (board_size * board_size) as usize], sizes: vec![], count: 0 }
+	}
+
+	pub fn from_pieces(board_size: i32, pieces: impl IntoIterator<Item=Coord>) -> Components {
+		let mut components = Components::new(board_size);
+		for coord in pieces {
+			components.insert(coord);
+		}
+		components
+	}
+
+	#[inline]
+	fn index(&self, coord: Coord) -> Option<usize> {
+		if 0 <= coord.0 && coord.0 < self.board_size && 0 <= coord.1 && coord.1 < self.board_size {
+			Some((coord.0 * self.board_size + coord.1) as usize)
+		} else {
+			None
+		}
+	}
+
+	#[inline]
+	fn coord(&self, index: usize) -> Coord {
+		Coord(index as i32 / self.board_size, index as i32 % self.board_size)
+	}
+
+	#[inline]
+	fn neighbor_indices(&self, coord: Coord) -> impl Iterator<Item=usize> + '_ {
+		let Coord(x, y) = coord;
+		[Coord(x+1, y), Coord(x-1, y), Coord(x, y+1), Coord(x, y-1)].into_iter()
+			.filter_map(|neighbor| self.index(neighbor))
+	}
+
+	fn new_id(&mut self) -> u8 {
+		self.count += 1;
+		match self.sizes.iter().position(|&size| size == 0) {
+			Some(id) => id as u8,
+			None => {
+				self.sizes.push(0);
+				(self.sizes.len() - 1) as u8
+			}
+		}
+	}
+
+	fn relabel(&mut self, from: u8, to: u8) {
+		for label in self.labels.iter_mut().filter(|label| **label == from) {
+			*label = to;
+		}
+		self.sizes[to as usize] += self.sizes[from as usize];
+		self.sizes[from as usize] = 0;
+		self.count -= 1;
+	}
+
+	/// Labels every cell reachable from `start` through cells labelled `from` as `to`.
+	fn fill(&mut self, start: usize, from: u8, to: u8) -> u8 {
+		let mut filled = 0;
+		let mut stack = vec![start];
+		self.labels[start] = to;
+		while let Some(index) = stack.pop() {
+			filled += 1;
+			let neighbors: Vec<_> = self.neighbor_indices(self.coord(index)).collect();
+			for neighbor in neighbors {
+				if self.labels[neighbor] == from {
+					self.labels[neighbor] = to;
+					stack.push(neighbor);
+				}
+			}
+		}
+		filled
+	}
+
+	pub fn insert(&mut self, coord: Coord) {
+		let index = self.index(coord).expect("Piece out of bounds");
+		if self.labels[index] != NO_COMPONENT {
+			return;
+		}
+		let mut neighbor_ids: Vec<u8> = self.neighbor_indices(coord)
+			.map(|neighbor| self.labels[neighbor])
+			.filter(|&id| id != NO_COMPONENT)
+			.collect();
+		neighbor_ids.sort();
+		neighbor_ids.dedup();
+		let id = match neighbor_ids.iter().max_by_key(|&&id| self.sizes[id as usize]) {
+			Some(&id) => id,
+			None => self.new_id()
+		};
+		for other in neighbor_ids.into_iter().filter(|&other| other != id) {
+			self.relabel(other, id);
+		}
+		self.labels[index] = id;
+		self.sizes[id as usize] += 1;
+	}
+
+	pub fn remove(&mut self, coord: Coord) {
+		let Some(index) = self.index(coord) else { return };
+		let id = self.labels[index];
+		if id == NO_COMPONENT {
+			return;
+		}
+		self.labels[index] = NO_COMPONENT;
+		self.sizes[id as usize] -= 1;
+		if self.sizes[id as usize] == 0 {
+			self.count -= 1;
+			return;
+		}
+		let neighbors: Vec<_> = self.neighbor_indices(coord)
+			.filter(|&neighbor| self.labels[neighbor] == id)
+			.collect();
+		if neighbors.len() <= 1 {
+			return;
+		}
+		// The group may have split: refill it from each former neighbor in turn
+		const UNVISITED: u8 = NO_COMPONENT - 1;
+		self.sizes[id as usize] = 0;
+		self.count -= 1;
+		for label in self.labels.iter_mut().filter(|label| **label == id) {
+			*label = UNVISITED;
+		}
+		for neighbor in neighbors {
+			if self.labels[neighbor] == UNVISITED {
+				let new_id = self.new_id();
+				self.sizes[new_id as usize] = self.fill(neighbor, UNVISITED, new_id);
+			}
+		}
+	}
+
+	/// Number of groups.
+	#[inline]
+	pub fn count(&self) -> usize {
+		self.count
+	}
+
+	/// Sizes of the groups, in no particular order.
+	#[inline]
+	pub fn sizes(&self) -> impl Iterator<Item=usize> + '_ {
+		self.sizes.iter().filter(|&&size| size > 0).map(|&size| size as usize)
+	}
+
+	/// Identifier of the group containing `coord`, stable until the next change.
+	#[inline]
+	pub fn component_of(&self, coord: Coord) -> Option<usize> {
+		self.index(coord)
+			.map(|index| self.labels[index])
+			.filter(|&id| id != NO_COMPONENT)
+			.map(|id| id as usize)
+	}
+
+	#[inline]
+	pub fn size_of(&self, coord: Coord) -> usize {
+		self.component_of(coord).map_or(0, |id| self.sizes[id] as usize)
+	}
+
+	/// Pieces in the group with identifier `id`.
+	pub fn members(&self, id: usize) -> impl Iterator<Item=Coord> + '_ {
+		self.labels.iter().enumerate()
+			.filter(move |(_, &label)| label as usize == id)
+			.map(|(index, _)| self.coord(index))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::board::*;
+	use crate::board::tests::random_positions;
+	use crate::symmetry::Symmetry;
+
+	#[test]
+	fn removal_splits_group() {
+		let mut components = Components::from_pieces(9, vec![Coord(0,0), Coord(1,0), Coord(2,0), Coord(1,1)]);
+		assert_eq!(1, components.count());
+		components.remove(Coord(1,0));
+		assert_eq!(3, components.count());
+		components.insert(Coord(2,1));
+		assert_eq!(2, components.count());
+		let mut sizes: Vec<_> = components.sizes().collect();
+		sizes.sort();
+		assert_eq!(vec![1, 3], sizes);
+		assert_eq!(components.component_of(Coord(1,1)), components.component_of(Coord(2,0)));
+		assert_eq!(None, components.component_of(Coord(1,0)));
+	}
+
+	/// Also covers boards whose pieces were replaced wholesale, which `Board::outcome` trusts
+	/// without checking.
+	#[test]
+	fn incremental_matches_flood_fill() {
+		let transformed = random_positions(1, 4, 40).into_iter()
+			.flat_map(|board| Symmetry::ALL.map(|symmetry| board.transform(symmetry)));
+		for board in transformed {
+			for color in [Color::White, Color::Black] {
+				let components = board.components_of(color);
+				let mut expected_sizes = vec![];
+				let mut pieces = board.pieces_of(color).clone();
+				while let Some(source) = pieces.iter().next().cloned() {
+					let group = board.flood_fill(color, source);
+					let id = components.component_of(source);
+					assert!(group.iter().all(|&coord| components.component_of(coord) == id));
+					assert_eq!(group.len(), components.members(id.unwrap()).count());
+					expected_sizes.push(group.len());
+					pieces = pieces.difference(group);
+				}
+				let mut sizes: Vec<_> = components.sizes().collect();
+				sizes.sort();
+				expected_sizes.sort();
+				assert_eq!(expected_sizes, sizes);
+			}
+		}
+	}
+}
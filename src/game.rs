@@ -0,0 +1,287 @@
+use crate::board::*;
+use crate::notation::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct DrawRules {
+	/// A position occurring this many times, with the same player to move, is a draw
+	pub repetitions: usize,
+	/// Plies in a row without a capture, conversion or placement before the game is drawn
+	pub no_progress_plies: usize,
+	/// Total plies before the game is drawn, if limited
+	pub max_plies: Option<usize>
+}
+
+impl Default for DrawRules {
+	fn default() -> Self {
+		DrawRules { repetitions: 3, no_progress_plies: 100, max_plies: None }
+	}
+}
+
+/// Why a game record cannot be replayed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecordError {
+	/// The move at this ply, counting from 0, is illegal
+	IllegalMove(usize, IllegalMoveReason),
+	/// The game ends with a different result than the record states
+	WrongResult(GameOutcome)
+}
+
+/// A game in progress: the boards reached by each move, with undone moves kept for redo
+/// until a different move is played.
+#[derive(Clone, Debug)]
+pub struct Game {
+	pub rules: DrawRules,
+	moves: Vec<Move>,
+	/// `boards[i]` is the position after `i` moves
+	boards: Vec<Board>,
+	/// Zobrist hashes of `boards`
+	hashes: Vec<u64>,
+	current: usize
+}
+
+impl Game {
+	pub fn new(board: Board, rules: DrawRules) -> Game {
+		let hashes = vec![board.zobrist_hash];
+		Game { rules, moves: vec![], boards: vec![board], hashes, current: 0 }
+	}
+
+	/// Replays a record from the start position, checking every move and the result. A game
+	/// still in progress may carry any result, since it may have been resigned or adjudicated.
+	pub fn from_record(record: &GameRecord, rules: DrawRules) -> Result<Game, RecordError> {
+		let mut game = Game::new(Board::start_position(9), rules);
+		for (ply, mov) in record.moves.iter().enumerate() {
+			game.try_play(mov).map_err(|reason| RecordError::IllegalMove(ply, reason))?;
+		}
+		match game.outcome() {
+			GameOutcome::Ongoing => Ok(game),
+			GameOutcome::Win(color) if record.winner == Some(color) => Ok(game),
+			GameOutcome::Draw(_) if record.winner.is_none() => Ok(game),
+			outcome => Err(RecordError::WrongResult(outcome))
+		}
+	}
+
+	/// The moves up to the current ply. Only wins are recorded as results. Records replay from
+	/// the start position, so there is none for a game that began elsewhere.
+	pub fn to_record(&self) -> Option<GameRecord> {
+		if *self.initial() != Board::start_position(9) {
+			return None;
+		}
+		Some(GameRecord { moves: self.moves().to_vec(), winner: self.board().winner() })
+	}
+
+	#[inline]
+	pub fn initial(&self) -> &Board {
+		&self.boards[0]
+	}
+
+	#[inline]
+	pub fn board(&self) -> &Board {
+		&self.boards[self.current]
+	}
+
+	#[inline]
+	pub fn ply(&self) -> usize {
+		self.current
+	}
+
+	/// Moves played up to the current ply.
+	#[inline]
+	pub fn moves(&self) -> &[Move] {
+		&self.moves[..self.current]
+	}
+
+	/// Zobrist hashes of every position up to and including the current one.
+	#[inline]
+	pub fn history(&self) -> &[u64] {
+		&self.hashes[..=self.current]
+	}
+
+	/// Each move up to the current ply with the board it produced.
+	pub fn iter(&self) -> impl Iterator<Item=(&Move, &Board)> + '_ {
+		self.moves().iter().zip(self.boards[1..=self.current].iter())
+	}
+
+	/// Number of times the current position has occurred.
+	pub fn repetitions(&self) -> usize {
+		let hash = self.board().zobrist_hash;
+		self.history().iter().filter(|&&other| other == hash).count()
+	}
+
+	pub fn plies_since_progress(&self) -> usize {
+		(1..=self.current).rev()
+			.take_while(|&ply| !makes_progress(&self.boards[ply - 1], &self.boards[ply]))
+			.count()
+	}
+
+	pub fn outcome(&self) -> GameOutcome {
+		match self.board().outcome() {
+			GameOutcome::Ongoing if self.repetitions() >= self.rules.repetitions =>
+				GameOutcome::Draw(DrawReason::Repetition),
+			GameOutcome::Ongoing if self.plies_since_progress() >= self.rules.no_progress_plies =>
+				GameOutcome::Draw(DrawReason::NoProgress),
+			GameOutcome::Ongoing if self.rules.max_plies.is_some_and(|max_plies| self.ply() >= max_plies) =>
+				GameOutcome::Draw(DrawReason::MoveLimit),
+			outcome => outcome
+		}
+	}
+
+	/// Plays `mov` at the current ply, discarding any undone moves.
+	pub fn play(&mut self, mov: &Move) -> GameOutcome {
+		assert_eq!(GameOutcome::Ongoing, self.outcome(), "Move played in a finished game");
+		let next = self.board().apply(mov);
+		self.push(mov, next);
+		self.outcome()
+	}
+
+	/// Checked `play` for moves from untrusted input.
+	pub fn try_play(&mut self, mov: &Move) -> Result<GameOutcome, IllegalMoveReason> {
+		if self.outcome() != GameOutcome::Ongoing {
+			return Err(IllegalMoveReason::GameOver);
+		}
+		let next = self.board().try_apply(mov)?;
+		self.push(mov, next);
+		Ok(self.outcome())
+	}
+
+	fn push(&mut self, mov: &Move, next: Board) {
+		self.moves.truncate(self.current);
+		self.boards.truncate(self.current + 1);
+		self.hashes.truncate(self.current + 1);
+		self.moves.push(mov.clone());
+		self.hashes.push(next.zobrist_hash);
+		self.boards.push(next);
+		self.current += 1;
+	}
+
+	/// Steps back one ply, returning the move taken back.
+	pub fn undo(&mut self) -> Option<&Move> {
+		if self.current == 0 {
+			return None;
+		}
+		self.current -= 1;
+		Some(&self.moves[self.current])
+	}
+
+	/// Replays the next undone move, if any.
+	pub fn redo(&mut self) -> Option<&Move> {
+		if self.current == self.moves.len() {
+			return None;
+		}
+		self.current += 1;
+		Some(&self.moves[self.current - 1])
+	}
+
+	/// Moves to any ply between the start and the last move played, keeping later moves for redo.
+	pub fn jump_to(&mut self, ply: usize) {
+		assert!(ply <= self.moves.len(), "Ply {ply} is past the last move {}", self.moves.len());
+		self.current = ply;
+	}
+}
+
+/// Whether a move changed either side's material on board or in reserve, which a plain
+/// movement cannot undo.
+pub fn makes_progress(before: &Board, after: &Board) -> bool {
+	before.white_reserve != after.white_reserve
+		|| before.black_reserve != after.black_reserve
+		|| before.white.len() != after.white.len()
+		|| before.black.len() != after.black.len()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn draw_by_repetition() {
+		let mut game = Game::new(Board::start_position(9), DrawRules::default());
+		let shuffle = [
+			Move::movement(Color::White, Coord(0,0), Coord(1,1)),
+			Move::movement(Color::Black, Coord(8,0), Coord(7,1)),
+			Move::movement(Color::White, Coord(2,2), Coord(1,1)),
+			Move::movement(Color::Black, Coord(6,2), Coord(7,1))
+		];
+		for mov in shuffle.iter().chain(shuffle.iter()) {
+			assert_eq!(GameOutcome::Ongoing, game.outcome());
+			game.play(mov);
+		}
+		assert_eq!(3, game.repetitions());
+		assert_eq!(GameOutcome::Draw(DrawReason::Repetition), game.outcome());
+		assert_eq!(None, game.board().winner());
+	}
+
+	#[test]
+	fn draw_without_progress() {
+		let rules = DrawRules { repetitions: 100, no_progress_plies: 3, max_plies: None };
+		let mut game = Game::new(Board::start_position(9), rules);
+		game.play(&Move::movement(Color::White, Coord(0,0), Coord(1,1)));
+		game.play(&Move::movement(Color::Black, Coord(8,0), Coord(7,1)));
+		assert_eq!(GameOutcome::Draw(DrawReason::NoProgress),
+			game.play(&Move::movement(Color::White, Coord(2,2), Coord(1,1))));
+	}
+
+	#[test]
+	fn undo_redo_and_jump() {
+		let record = parse_game(concat!(
+			"1. 1一5三 1九3五\n",
+			"2. 1二3四 2九2五\n",
+			"3. 3二3六*\n"
+		)).unwrap().1;
+		let mut game = Game::from_record(&record, DrawRules::default()).unwrap();
+		assert_eq!(5, game.ply());
+		let final_board = game.board().clone();
+
+		assert_eq!(Some(&record.moves[4]), game.undo());
+		assert_eq!(Some(&record.moves[3]), game.undo());
+		assert_eq!(3, game.ply());
+		assert_eq!(&record.moves[..3], game.moves());
+		assert_eq!(Some(&record.moves[3]), game.redo());
+		assert_eq!(Some(&record.moves[4]), game.redo());
+		assert_eq!(None, game.redo());
+		assert_eq!(&final_board, game.board());
+
+		game.jump_to(0);
+		assert_eq!(game.initial(), game.board());
+		assert_eq!(None, game.undo());
+		game.jump_to(2);
+		let boards: Vec<_> = game.iter().map(|(_, board)| board.clone()).collect();
+		assert_eq!(2, boards.len());
+		assert_eq!(Board::start_position(9).apply(&record.moves[0]).apply(&record.moves[1]), boards[1]);
+
+		game.play(&record.moves[2]);
+		game.play(&Move::movement(Color::Black, Coord(2,8), Coord(2,7)));
+		assert_eq!(None, game.redo());
+		assert_eq!(4, game.to_record().unwrap().moves.len());
+
+		let elsewhere = Game::new(Board::start_position(9).apply(&record.moves[0]), DrawRules::default());
+		assert_eq!(None, elsewhere.to_record());
+	}
+
+	#[test]
+	fn record_round_trip() {
+		let text = concat!(
+			"1. 1一5三 1九3五\n",
+			"2. 1二3四 2九2五\n",
+			"3. 3二3六* 3九3七\n"
+		);
+		let game = Game::from_record(&parse_game(text).unwrap().1, DrawRules::default()).unwrap();
+		assert_eq!(text, format_game(&game.to_record().unwrap()));
+
+		let illegal = parse_game("1. 1一5三 1一5三\n").unwrap().1;
+		assert_eq!(RecordError::IllegalMove(1, IllegalMoveReason::ActiveNotOwned), Game::from_record(&illegal, DrawRules::default()).unwrap_err());
+
+		let shuffle = vec![
+			Move::movement(Color::White, Coord(0,0), Coord(1,1)),
+			Move::movement(Color::Black, Coord(8,0), Coord(7,1)),
+			Move::movement(Color::White, Coord(2,2), Coord(1,1)),
+			Move::movement(Color::Black, Coord(6,2), Coord(7,1))
+		];
+		let drawn = GameRecord { moves: [shuffle.clone(), shuffle].concat(), winner: None };
+		assert!(Game::from_record(&drawn, DrawRules::default()).is_ok());
+		let claimed = GameRecord { winner: Some(Color::Black), ..drawn.clone() };
+		assert_eq!(RecordError::WrongResult(GameOutcome::Draw(DrawReason::Repetition)),
+			Game::from_record(&claimed, DrawRules::default()).unwrap_err());
+		let mut continued = drawn;
+		continued.moves.push(Move::movement(Color::White, Coord(0,0), Coord(1,1)));
+		assert_eq!(RecordError::IllegalMove(8, IllegalMoveReason::GameOver), Game::from_record(&continued, DrawRules::default()).unwrap_err());
+	}
+}
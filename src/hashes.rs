@@ -17,7 +17,7 @@ pub fn piece_hash(color: Color, coord: Coord) -> u64 {
 
 pub fn reserve_hash(color: Color, reserve: i32) -> u64 {
 	assert!(0 <= reserve);
-	RESERVE_HASHES[20*color_to_num(color) + (reserve as usize)]
+	RESERVE_HASHES[21*color_to_num(color) + (reserve as usize)]
 }
 
 pub fn turn_hash(color: Color) -> u64 {
@@ -1,4 +1,15 @@
 pub mod ai;
+pub mod arena;
 pub mod board;
+pub mod book;
+pub mod components;
+pub mod game;
+pub mod movegen;
 pub mod notation;
+pub mod patterns;
+pub mod perft;
+pub mod registry;
+pub mod solver;
+pub mod symmetry;
 pub mod hashes;
+pub mod tuning;
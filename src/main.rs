@@ -1,30 +1,294 @@
 pub mod ai;
+pub mod arena;
 pub mod board;
+pub mod book;
+pub mod components;
+pub mod game;
+pub mod movegen;
 pub mod notation;
+pub mod patterns;
+pub mod perft;
+pub mod registry;
+pub mod solver;
+pub mod symmetry;
 pub mod hashes;
+pub mod tuning;
 
 use ai::*;
 use board::*;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use std::env;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn main() {
-	let mut board = Board::start_position(9);
-	let heuristic = LinearCombinationHeuristic{terms: vec![
-		(1, Box::new(CentroidDistanceHeuristic {power: 2.0})),
-		(1, Box::new(PieceCountHeuristic {})),
-		(5, Box::new(ConnectedComponentsHeuristic {}))
-	]};
+fn configured_heuristic(config: &str) -> LinearCombinationHeuristic {
+	registry::parse_heuristic(config).unwrap_or_else(|err| {
+		eprintln!("{err}");
+		process::exit(1);
+	})
+}
+
+fn usage() -> ! {
+	eprintln!("Usage:");
+	eprintln!("  --stats may be given anywhere to print search statistics");
+	eprintln!("  --heuristic=CONFIG may be given anywhere to choose the evaluator, e.g.");
+	eprintln!("                              --heuristic='5*connected_components + 1*centroid(power=2)'");
+	eprintln!("  --patterns=FILE may be given anywhere to add a pattern table to the evaluator");
+	eprintln!("  --solver=SETTINGS may be given anywhere to score proven forced wins at search leaves, e.g.");
+	eprintln!("                              --solver='max_stragglers=1, plies=3'");
+	eprintln!("  quorum [play [WEIGHTS [BOOK]]]");
+	eprintln!("                              self-play from the start position");
+	eprintln!("  quorum book CORPUS BOOK [PLIES]");
+	eprintln!("                              build an opening book from finished games");
+	eprintln!("  quorum tune CORPUS WEIGHTS  fit heuristic weights to finished games");
+	eprintln!("  quorum perft DEPTH          count move sequences from the start position");
+	eprintln!("  quorum arena ENGINE_A ENGINE_B [GAMES]");
+	eprintln!("                              match between two engines, each a weights file or settings");
+	eprintln!("                              such as 'heuristic=CONFIG;weights=FILE;depth=3;time=500;book=FILE;solver=plies=3'");
+	eprintln!("  quorum heuristics           list the heuristics a config can use");
+	eprintln!("  quorum patterns CORPUS PATTERNS");
+	eprintln!("                              fit 3x3 pattern weights to finished games");
+	eprintln!("  quorum selfplay GAMES PATTERNS");
+	eprintln!("                              fit 3x3 pattern weights to games the engine plays itself");
+	eprintln!("  quorum explain POSITION [WEIGHTS]");
+	eprintln!("                              show each term's part in the evaluation of a position");
+	process::exit(2);
+}
+
+fn load_patterns(patterns_path: &str) -> patterns::PatternHeuristic {
+	patterns::PatternHeuristic::load(patterns_path).unwrap_or_else(|err| {
+		eprintln!("Could not read {patterns_path}: {err}");
+		process::exit(1);
+	})
+}
+
+fn fit_patterns(records: &[notation::GameRecord], patterns_path: &str) {
+	let trainer = patterns::PatternTrainer::default();
+	let positions = trainer.extract_positions(records);
+	println!("{} positions from {} games", positions.len(), records.len());
+	let heuristic = trainer.to_heuristic(&trainer.fit(&positions));
+	println!("{} patterns with nonzero weight", heuristic.weights.iter().filter(|weight| **weight != 0).count());
+	heuristic.save(patterns_path).unwrap_or_else(|err| {
+		eprintln!("Could not write {patterns_path}: {err}");
+		process::exit(1);
+	});
+}
+
+fn train_patterns(corpus_path: &str, patterns_path: &str) {
+	let records = tuning::load_corpus(corpus_path).unwrap_or_else(|err| {
+		eprintln!("Could not read {corpus_path}: {err}");
+		process::exit(1);
+	});
+	fit_patterns(&records, patterns_path);
+}
+
+fn self_play_patterns(games: &str, patterns_path: &str, heuristic: LinearCombinationHeuristic, solver: Option<solver::SolverSettings>) {
+	let games: usize = games.parse().unwrap_or_else(|_| usage());
+	let engine = arena::EngineConfig { name: "self".to_string(), heuristic, depth: arena::EngineSpec::DEFAULT_DEPTH, time_budget: None, book: None, solver };
+	let arena = arena::Arena::default();
+	let mut rng = clock_rng();
+	let records: Vec<_> = (0..games).map(|game| {
+		let record = arena.self_play_record(&engine, &mut rng);
+		println!("Game {}: {} plies, {:?}", game + 1, record.moves.len(), record.winner);
+		record
+	}).collect();
+	fit_patterns(&records, patterns_path);
+}
+
+fn weighted_heuristic(config: &str, weights_path: &str) -> LinearCombinationHeuristic {
+	let weights = tuning::read_weights(weights_path).unwrap_or_else(|err| {
+		eprintln!("Could not read {weights_path}: {err}");
+		process::exit(1);
+	});
+	let mut heuristic = configured_heuristic(config);
+	if weights.len() != heuristic.terms.len() {
+		eprintln!("{weights_path} has {} weights but the heuristic has {} terms", weights.len(), heuristic.terms.len());
+		process::exit(1);
+	}
+	heuristic.set_weights(&weights);
+	heuristic
+}
+
+fn load_book(book_path: &str) -> book::OpeningBook {
+	book::OpeningBook::load(book_path).unwrap_or_else(|err| {
+		eprintln!("Could not read {book_path}: {err}");
+		process::exit(1);
+	})
+}
+
+fn clock_rng() -> ChaCha12Rng {
+	let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+	ChaCha12Rng::seed_from_u64(nanos)
+}
+
+fn play<H: Heuristic>(heuristic: &H, book: Option<&book::OpeningBook>, show_stats: bool) {
+	let mut rng = clock_rng();
+	let rules = game::DrawRules { max_plies: Some(50), ..game::DrawRules::default() };
+	let mut game = game::Game::new(Board::start_position(9), rules);
 	{
 		let mut tt = TRANSPOSITION_TABLE.write().unwrap();
 		*tt = TranspositionTable::new();
 	}
+	game.board().show_board();
+	while game.outcome() == GameOutcome::Ongoing {
+		let board = game.board();
+		let next_move = book.and_then(|book| book.choose(board, &mut rng))
+			.unwrap_or_else(|| {
+				let (mov, stats) = best_move_with_history(board, game.history(), 3, heuristic);
+				if show_stats {
+					stats.print();
+				}
+				mov.unwrap()
+			});
+		let ply = game.ply();
+		game.play(&next_move);
+		println!("{ply} {}", heuristic.heuristic(game.board()));
+		game.board().show_board();
+	}
+	println!("{:?}", game.outcome());
+}
+
+fn tune(corpus_path: &str, weights_path: &str, heuristic: &LinearCombinationHeuristic) {
+	let tuner = tuning::TexelTuner::default();
+	let records = tuning::load_corpus(corpus_path).unwrap_or_else(|err| {
+		eprintln!("Could not read {corpus_path}: {err}");
+		process::exit(1);
+	});
+	let positions = tuner.extract_positions(&records, heuristic);
+	println!("{} positions from {} games", positions.len(), records.len());
+	let fitted = tuner.fit(&positions);
+	println!("Fitted weights {fitted:?}, error {}", tuning::mean_squared_error(&positions, &fitted));
+	let weights = tuning::to_valuation_weights(&fitted);
+	println!("Valuation weights {weights:?}");
+	tuning::write_weights(weights_path, &weights).unwrap_or_else(|err| {
+		eprintln!("Could not write {weights_path}: {err}");
+		process::exit(1);
+	});
+}
+
+fn build_book(corpus_path: &str, book_path: &str, plies: Option<&str>) {
+	let records = tuning::load_corpus(corpus_path).unwrap_or_else(|err| {
+		eprintln!("Could not read {corpus_path}: {err}");
+		process::exit(1);
+	});
+	let plies = plies.map_or(16, |plies| plies.parse().unwrap_or_else(|_| usage()));
+	let book = book::OpeningBook::from_records(&records, plies);
+	println!("{} positions from {} games", book.positions.len(), records.len());
+	book.save(book_path).unwrap_or_else(|err| {
+		eprintln!("Could not write {book_path}: {err}");
+		process::exit(1);
+	});
+}
+
+fn run_perft(depth: &str) {
+	let depth = depth.parse().ok().filter(|&depth| depth >= 1).unwrap_or_else(|| usage());
+	let board = Board::start_position(9);
+	let mut total = 0;
+	for (mov, count) in perft::divide(&board, depth) {
+		println!("{mov:?}: {count}");
+		total += count;
+	}
+	println!("perft({depth}) = {total}");
+}
+
+fn arena_engine(config: &str, spec: &str) -> arena::EngineConfig {
+	let engine = arena::EngineSpec::parse(spec, config).unwrap_or_else(|err| {
+		eprintln!("{err}");
+		process::exit(1);
+	});
+	let heuristic = match &engine.weights {
+		Some(weights_path) => weighted_heuristic(&engine.heuristic, weights_path),
+		None => configured_heuristic(&engine.heuristic)
+	};
+	let book = engine.book.as_deref().map(load_book);
+	arena::EngineConfig { name: spec.to_string(), heuristic, depth: engine.depth, time_budget: engine.time_budget, book, solver: engine.solver }
+}
+
+fn run_arena(config: &str, first_spec: &str, second_spec: &str, games: Option<&str>) {
+	let first = arena_engine(config, first_spec);
+	let second = arena_engine(config, second_spec);
+	let mut arena = arena::Arena::default();
+	if let Some(games) = games {
+		arena.max_games = games.parse().unwrap_or_else(|_| usage());
+	}
+	let sprt = arena.sprt.unwrap_or_default();
+	let result = arena.run(&first, &second, |result, _| {
+		println!("{} vs {}: +{} ={} -{} LLR {:.2}",
+			first.name, second.name, result.wins, result.draws, result.losses, result.llr(&sprt));
+	});
+	let (low, high) = result.elo_interval();
+	println!("Elo {:.1} [{:.1}, {:.1}] after {} games, SPRT {:?}",
+		result.elo(), low, high, result.games(), result.sprt_status(&sprt));
+}
+
+fn list_heuristics() {
+	for info in registry::REGISTRY {
+		let parameters: Vec<_> = info.parameters.iter().map(|parameter| format!("{}={}", parameter.name, parameter.default)).collect();
+		let signature = if parameters.is_empty() { info.name.to_string() } else { format!("{}({})", info.name, parameters.join(", ")) };
+		println!("  {signature:<48} {}", info.description);
+	}
+}
+
+fn explain<H: Heuristic>(position: &str, heuristic: &H) {
+	let board = match notation::parse_position(position) {
+		Ok(("", board)) => board,
+		_ => {
+			eprintln!("Could not parse position {position:?}");
+			process::exit(1);
+		}
+	};
 	board.show_board();
-	for ply in 0..50 {
-		let next_move = best_move(&board, 3, &heuristic).unwrap();
-		board = board.apply(&next_move);
-		println!("{ply} {}", heuristic.heuristic(&board));
-		board.show_board();
-		if board.winner().is_some() {
-			break;
+	println!("{:<48} {:>8} {:>8} {:>12}", "term", "value", "weight", "contribution");
+	for term in heuristic.explain(&board) {
+		println!("{:<48} {:>8} {:>8} {:>12}", term.name, term.value, term.weight, term.contribution);
+	}
+	println!("{:<48} {:>8} {:>8} {:>12}", "total", "", "", heuristic.heuristic(&board));
+}
+
+pub fn main() {
+	let mut args: Vec<String> = env::args().skip(1).collect();
+	let show_stats = args.iter().any(|arg| arg == "--stats");
+	args.retain(|arg| arg != "--stats");
+	let config = args.iter().rev().find_map(|arg| arg.strip_prefix("--heuristic=")).unwrap_or(registry::DEFAULT_HEURISTIC).to_string();
+	args.retain(|arg| !arg.starts_with("--heuristic="));
+	let patterns_path = args.iter().rev().find_map(|arg| arg.strip_prefix("--patterns=")).map(str::to_string);
+	args.retain(|arg| !arg.starts_with("--patterns="));
+	let solver = args.iter().rev().find_map(|arg| arg.strip_prefix("--solver=")).map(|settings| {
+		solver::SolverSettings::parse(settings).unwrap_or_else(|err| {
+			eprintln!("{err}");
+			process::exit(1);
+		})
+	});
+	args.retain(|arg| !arg.starts_with("--solver="));
+	let with_patterns = |mut heuristic: LinearCombinationHeuristic| {
+		if let Some(patterns_path) = &patterns_path {
+			heuristic.terms.push((1, Box::new(load_patterns(patterns_path))));
+		}
+		heuristic
+	};
+	let evaluator = |heuristic: LinearCombinationHeuristic| -> Box<dyn Heuristic> {
+		let heuristic = with_patterns(heuristic);
+		match solver {
+			Some(solver) => Box::new(solver.extend(heuristic)),
+			None => Box::new(heuristic)
 		}
+	};
+	match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+		[] | ["play"] => play(&evaluator(configured_heuristic(&config)), None, show_stats),
+		["play", weights_path] => play(&evaluator(weighted_heuristic(&config, weights_path)), None, show_stats),
+		["play", weights_path, book_path] => play(&evaluator(weighted_heuristic(&config, weights_path)), Some(&load_book(book_path)), show_stats),
+		["tune", corpus_path, weights_path] => tune(corpus_path, weights_path, &configured_heuristic(&config)),
+		["book", corpus_path, book_path] => build_book(corpus_path, book_path, None),
+		["book", corpus_path, book_path, plies] => build_book(corpus_path, book_path, Some(plies)),
+		["perft", depth] => run_perft(depth),
+		["arena", first_spec, second_spec] => run_arena(&config, first_spec, second_spec, None),
+		["arena", first_spec, second_spec, games] => run_arena(&config, first_spec, second_spec, Some(games)),
+		["heuristics"] => list_heuristics(),
+		["explain", position] => explain(position, &evaluator(configured_heuristic(&config))),
+		["explain", position, weights_path] => explain(position, &evaluator(weighted_heuristic(&config, weights_path))),
+		["patterns", corpus_path, patterns_path] => train_patterns(corpus_path, patterns_path),
+		["selfplay", games, patterns_path] => self_play_patterns(games, patterns_path, with_patterns(configured_heuristic(&config)), solver),
+		_ => usage()
 	}
 }
@@ -0,0 +1,544 @@
+use crate::board::*;
+
+use std::cmp;
+use tinyvec::ArrayVec;
+
+const MAX_BOARD_SIZE: i32 = 9;
+const MAX_CELLS: usize = (MAX_BOARD_SIZE * MAX_BOARD_SIZE) as usize;
+
+/// Offsets of the eight neighbours of a square, in the order `Board::neighborhood` visits them.
+/// Bit `i` of a conversion mask refers to the neighbour of the destination at `NEIGHBOR_OFFSETS[i]`.
+pub const NEIGHBOR_OFFSETS: [(i32, i32); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+/// A move that owns no heap memory: conversions are a bitmask over the neighbours of the
+/// destination, as laid out in `NEIGHBOR_OFFSETS`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum CompactMove {
+	Movement { color: Color, active: Coord, pivot: Coord, conversions: u8 },
+	Placement { color: Color, at: Coord }
+}
+
+impl Default for CompactMove {
+	fn default() -> Self {
+		CompactMove::Placement { color: Color::White, at: Coord(0, 0) }
+	}
+}
+
+impl CompactMove {
+	#[inline]
+	pub fn dest(&self) -> Coord {
+		match self {
+			CompactMove::Movement { active, pivot, .. } => Coord(2*pivot.0 - active.0, 2*pivot.1 - active.1),
+			CompactMove::Placement { at, .. } => *at
+		}
+	}
+
+	#[inline]
+	pub fn color(&self) -> Color {
+		match self {
+			CompactMove::Movement { color, .. } | CompactMove::Placement { color, .. } => *color
+		}
+	}
+
+	/// Coordinates of the converted pieces, in `NEIGHBOR_OFFSETS` order.
+	pub fn conversion_coords(&self) -> impl Iterator<Item=Coord> {
+		let (dest, mask) = match self {
+			CompactMove::Movement { conversions, .. } => (self.dest(), *conversions),
+			CompactMove::Placement { .. } => (Coord(0, 0), 0)
+		};
+		NEIGHBOR_OFFSETS.into_iter().enumerate()
+			.filter(move |(i, _)| mask & (1 << i) != 0)
+			.map(move |(_, (dx, dy))| Coord(dest.0 + dx, dest.1 + dy))
+	}
+
+	pub fn to_move(&self) -> Move {
+		match *self {
+			CompactMove::Movement { color, active, pivot, .. } =>
+				Move::Movement { color, active, pivot, conversions: self.conversion_coords().collect() },
+			CompactMove::Placement { color, at } => Move::Placement { color, at }
+		}
+	}
+
+	/// Fails if a conversion is not a neighbour of the destination.
+	pub fn from_move(mov: &Move) -> Option<CompactMove> {
+		match mov {
+			Move::Movement { color, active, pivot, conversions } => {
+				let dest = mov.dest();
+				let mut mask = 0u8;
+				for converted in conversions {
+					let offset = (converted.0 - dest.0, converted.1 - dest.1);
+					mask |= 1 << NEIGHBOR_OFFSETS.iter().position(|&neighbor| neighbor == offset)?;
+				}
+				Some(CompactMove::Movement { color: *color, active: *active, pivot: *pivot, conversions: mask })
+			},
+			Move::Placement { color, at } => Some(CompactMove::Placement { color: *color, at: *at })
+		}
+	}
+}
+
+/// A move packed into 32 bits, for tables that store many moves. From the low bit up: kind
+/// (1 for movements), color (1 for white), active square or placement square, pivot square
+/// and conversion mask, each square taking a byte of two 4-bit coordinates.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub struct PackedMove(pub u32);
+
+impl PackedMove {
+	/// Whether both coordinates fit in four bits.
+	#[inline]
+	fn fits(coord: Coord) -> bool {
+		(0..16).contains(&coord.0) && (0..16).contains(&coord.1)
+	}
+
+	#[inline]
+	fn pack_coord(coord: Coord) -> u32 {
+		debug_assert!(PackedMove::fits(coord));
+		((coord.0 as u32) << 4) | coord.1 as u32
+	}
+
+	#[inline]
+	fn unpack_coord(bits: u32) -> Coord {
+		Coord(((bits >> 4) & 0xf) as i32, (bits & 0xf) as i32)
+	}
+
+	#[inline]
+	pub fn is_movement(&self) -> bool {
+		self.0 & 1 != 0
+	}
+
+	#[inline]
+	pub fn color(&self) -> Color {
+		if self.0 & 2 != 0 { Color::White } else { Color::Black }
+	}
+
+	pub fn to_compact(&self) -> CompactMove {
+		let color = self.color();
+		let first = PackedMove::unpack_coord(self.0 >> 2);
+		if self.is_movement() {
+			let pivot = PackedMove::unpack_coord(self.0 >> 10);
+			CompactMove::Movement { color, active: first, pivot, conversions: (self.0 >> 18) as u8 }
+		} else {
+			CompactMove::Placement { color, at: first }
+		}
+	}
+
+	/// Unpacks the move as played on `board`.
+	pub fn to_move(&self, board: &Board) -> Move {
+		let mov = self.to_compact().to_move();
+		debug_assert!(match &mov {
+			Move::Movement { active, pivot, .. } => board.in_bounds(*active) && board.in_bounds(*pivot),
+			Move::Placement { at, .. } => board.in_bounds(*at)
+		});
+		mov
+	}
+}
+
+/// Why a `Move` has no `PackedMove` encoding.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PackMoveError {
+	/// A square with a coordinate outside `0..16`
+	CoordOutOfRange(Coord),
+	/// A conversion that is not next to the destination, which no legal move has
+	ConversionNotAdjacent(Coord)
+}
+
+impl TryFrom<CompactMove> for PackedMove {
+	type Error = PackMoveError;
+
+	fn try_from(mov: CompactMove) -> Result<PackedMove, PackMoveError> {
+		let squares = match mov {
+			CompactMove::Movement { active, pivot, .. } => vec![active, pivot],
+			CompactMove::Placement { at, .. } => vec![at]
+		};
+		if let Some(coord) = squares.into_iter().find(|coord| !PackedMove::fits(*coord)) {
+			return Err(PackMoveError::CoordOutOfRange(coord));
+		}
+		let color_bit = match mov.color() { Color::Black => 0, Color::White => 2 };
+		Ok(match mov {
+			CompactMove::Movement { active, pivot, conversions, .. } => PackedMove(1 | color_bit
+				| PackedMove::pack_coord(active) << 2
+				| PackedMove::pack_coord(pivot) << 10
+				| (conversions as u32) << 18),
+			CompactMove::Placement { at, .. } => PackedMove(color_bit | PackedMove::pack_coord(at) << 2)
+		})
+	}
+}
+
+impl TryFrom<&Move> for PackedMove {
+	type Error = PackMoveError;
+
+	fn try_from(mov: &Move) -> Result<PackedMove, PackMoveError> {
+		if let Move::Movement { conversions, .. } = mov {
+			let dest = mov.dest();
+			if let Some(converted) = conversions.iter().find(|converted| {
+				!NEIGHBOR_OFFSETS.contains(&(converted.0 - dest.0, converted.1 - dest.1))
+			}) {
+				return Err(PackMoveError::ConversionNotAdjacent(*converted));
+			}
+		}
+		PackedMove::try_from(CompactMove::from_move(mov).expect("Conversions checked above"))
+	}
+}
+
+impl TryFrom<Move> for PackedMove {
+	type Error = PackMoveError;
+
+	#[inline]
+	fn try_from(mov: Move) -> Result<PackedMove, PackMoveError> {
+		PackedMove::try_from(&mov)
+	}
+}
+
+/// One bit per square, indexed like `Components`.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+struct Bitboard(u128);
+
+impl Bitboard {
+	#[inline]
+	fn index(coord: Coord) -> u32 {
+		(coord.0 * MAX_BOARD_SIZE + coord.1) as u32
+	}
+
+	#[inline]
+	fn contains(&self, coord: Coord) -> bool {
+		self.0 & (1 << Bitboard::index(coord)) != 0
+	}
+
+	#[inline]
+	fn insert(&mut self, coord: Coord) {
+		self.0 |= 1 << Bitboard::index(coord);
+	}
+}
+
+/// Capture and conversion candidates around a destination square.
+#[derive(Copy, Clone, Default)]
+struct Candidates {
+	captures: u8,
+	conversions: u8
+}
+
+/// Lazily generates the moves of one color without heap allocation. Movements come first,
+/// ordered by active piece and then pivot, followed by placements.
+pub struct MoveGenerator<'a> {
+	board: &'a Board,
+	color: Color,
+	reserve: i32,
+	own: Bitboard,
+	opponent: Bitboard,
+	pieces: ArrayVec<[Coord; MAX_CELLS]>,
+	active_index: usize,
+	pivot_index: usize,
+	candidates: [Option<Candidates>; MAX_CELLS],
+	/// Pending conversion subsets for the current (active, pivot)
+	pending: Option<PendingConversions>,
+	placement_index: i32
+}
+
+#[derive(Copy, Clone)]
+struct PendingConversions {
+	active: Coord,
+	pivot: Coord,
+	captures: u8,
+	candidates: u8,
+	/// Next subset of `candidates` to try; `None` once exhausted
+	next: Option<u8>
+}
+
+impl<'a> MoveGenerator<'a> {
+	pub fn new(board: &'a Board, color: Color) -> MoveGenerator<'a> {
+		assert!(board.board_size <= MAX_BOARD_SIZE);
+		let mut own = Bitboard::default();
+		let mut opponent = Bitboard::default();
+		let mut pieces = ArrayVec::new();
+		for coord in board.pieces_of(color).iter() {
+			own.insert(*coord);
+			pieces.push(*coord);
+		}
+		for coord in board.pieces_of(color.opponent()).iter() {
+			opponent.insert(*coord);
+		}
+		MoveGenerator {
+			board, color, reserve: board.reserve_of(color), own, opponent, pieces,
+			active_index: 0, pivot_index: 0, candidates: [None; MAX_CELLS], pending: None, placement_index: 0
+		}
+	}
+
+	#[inline]
+	fn occupied(&self, coord: Coord) -> bool {
+		self.own.contains(coord) || self.opponent.contains(coord)
+	}
+
+	/// Same rules as `Board::capturable_around` and `Board::convertible_around`. Neither
+	/// depends on the active piece, since it can never be next to the destination nor be the
+	/// flanker of an enemy piece, so they are computed once per destination.
+	fn candidates_at(&mut self, dest: Coord) -> Candidates {
+		let index = Bitboard::index(dest) as usize;
+		if let Some(candidates) = self.candidates[index] {
+			return candidates;
+		}
+		let mut candidates = Candidates::default();
+		for (i, (dx, dy)) in NEIGHBOR_OFFSETS.into_iter().enumerate() {
+			let neighbor = Coord(dest.0 + dx, dest.1 + dy);
+			if !self.board.in_bounds(neighbor) || !self.opponent.contains(neighbor) {
+				continue;
+			}
+			let surrounded = NEIGHBOR_OFFSETS.iter()
+				.map(|(lx, ly)| Coord(neighbor.0 + lx, neighbor.1 + ly))
+				.filter(|liberty| self.board.in_bounds(*liberty))
+				.all(|liberty| liberty == dest || self.occupied(liberty));
+			if surrounded {
+				candidates.captures |= 1 << i;
+			}
+			let flanker = Coord(neighbor.0 + dx, neighbor.1 + dy);
+			if self.board.in_bounds(flanker) && self.own.contains(flanker) {
+				candidates.conversions |= 1 << i;
+			}
+		}
+		self.candidates[index] = Some(candidates);
+		candidates
+	}
+
+	#[inline]
+	fn movement_dest(&self, active: Coord, pivot: Coord) -> Option<Coord> {
+		if active == pivot {
+			return None;
+		}
+		let dest = Coord(2*pivot.0 - active.0, 2*pivot.1 - active.1);
+		let gap = cmp::max((active.0 - pivot.0).abs() - 1, (active.1 - pivot.1).abs() - 1);
+		(self.board.in_bounds(dest) && !self.occupied(dest) && gap <= self.board.max_gap).then_some(dest)
+	}
+
+	/// Next legal conversion subset of the pending movement, matching `Board::moves_of`:
+	/// every candidate that is not capturable is converted if the reserve allows, otherwise
+	/// each subset of exactly `reserve` of them is offered.
+	fn next_pending(&mut self) -> Option<CompactMove> {
+		let pending = self.pending.as_mut()?;
+		let size = cmp::min((pending.candidates & !pending.captures).count_ones() as i32, self.reserve) as u32;
+		while let Some(subset) = pending.next {
+			pending.next = if subset == 0 { None } else { Some((subset - 1) & pending.candidates) };
+			if subset.count_ones() == size && subset & pending.captures == 0 {
+				return Some(CompactMove::Movement { color: self.color, active: pending.active, pivot: pending.pivot, conversions: subset });
+			}
+		}
+		self.pending = None;
+		None
+	}
+
+	fn next_movement(&mut self) -> Option<CompactMove> {
+		loop {
+			if let Some(mov) = self.next_pending() {
+				return Some(mov);
+			}
+			let active = *self.pieces.get(self.active_index)?;
+			let pivot = self.pieces[self.pivot_index];
+			self.pivot_index += 1;
+			if self.pivot_index == self.pieces.len() {
+				self.pivot_index = 0;
+				self.active_index += 1;
+			}
+			if let Some(dest) = self.movement_dest(active, pivot) {
+				let candidates = self.candidates_at(dest);
+				self.pending = Some(PendingConversions {
+					active, pivot, captures: candidates.captures, candidates: candidates.conversions, next: Some(candidates.conversions)
+				});
+			}
+		}
+	}
+
+	fn next_placement(&mut self) -> Option<CompactMove> {
+		if self.reserve <= 0 {
+			return None;
+		}
+		let size = self.board.board_size;
+		while self.placement_index < size * size {
+			let at = Coord(self.placement_index / size, self.placement_index % size);
+			self.placement_index += 1;
+			if !self.occupied(at) {
+				return Some(CompactMove::Placement { color: self.color, at });
+			}
+		}
+		None
+	}
+}
+
+impl Iterator for MoveGenerator<'_> {
+	type Item = CompactMove;
+
+	#[inline]
+	fn next(&mut self) -> Option<CompactMove> {
+		self.next_movement().or_else(|| self.next_placement())
+	}
+}
+
+/// Number of ways to choose `k` items out of `n`.
+#[inline]
+fn binomial(n: u32, k: u32) -> usize {
+	if k > n {
+		return 0;
+	}
+	(0..k).fold(1, |acc, i| acc * (n - i) as usize / (i + 1) as usize)
+}
+
+impl MoveGenerator<'_> {
+	/// Number of movements `next_pending` yields for a destination with these candidates.
+	#[inline]
+	fn conversion_choices(&self, candidates: Candidates) -> usize {
+		let choosable = (candidates.conversions & !candidates.captures).count_ones();
+		binomial(choosable, cmp::min(choosable as i32, self.reserve) as u32)
+	}
+
+	/// Number of legal moves, computed without enumerating conversion subsets.
+	pub fn count_moves(mut self) -> usize {
+		let mut count = 0;
+		for active in self.pieces {
+			for pivot in self.pieces {
+				if let Some(dest) = self.movement_dest(active, pivot) {
+					let candidates = self.candidates_at(dest);
+					count += self.conversion_choices(candidates);
+				}
+			}
+		}
+		if self.reserve > 0 {
+			let size = self.board.board_size;
+			count += (size * size) as usize - self.pieces.len() - self.board.pieces_of(self.color.opponent()).len();
+		}
+		count
+	}
+
+	/// One `(active, dest)` pair for each distinct empty square some movement can land on.
+	pub fn movement_targets(mut self) -> ArrayVec<[(Coord, Coord); MAX_CELLS]> {
+		let mut destinations = Bitboard::default();
+		let mut targets = ArrayVec::new();
+		for active in self.pieces {
+			for pivot in self.pieces {
+				if let Some(dest) = self.movement_dest(active, pivot) {
+					let candidates = self.candidates_at(dest);
+					if !destinations.contains(dest) && self.conversion_choices(candidates) > 0 {
+						destinations.insert(dest);
+						targets.push((active, dest));
+					}
+				}
+			}
+		}
+		targets
+	}
+
+	/// Number of distinct empty squares some movement can land on.
+	#[inline]
+	pub fn count_destinations(self) -> usize {
+		self.movement_targets().len()
+	}
+}
+
+/// A fixed-capacity move list that never allocates. No useful bound on the moves of a position
+/// is known, so a position is read in chunks of at most `CAPACITY` moves, which in play is
+/// nearly always one chunk.
+#[derive(Clone, Default, Debug)]
+pub struct MoveBuffer {
+	moves: ArrayVec<[CompactMove; MoveBuffer::CAPACITY]>
+}
+
+impl MoveBuffer {
+	pub const CAPACITY: usize = 256;
+
+	pub fn new() -> MoveBuffer {
+		MoveBuffer::default()
+	}
+
+	/// Replaces the contents with the next moves of `generator`, up to `CAPACITY` of them.
+	/// Empty once the generator is exhausted.
+	pub fn fill(&mut self, generator: &mut MoveGenerator) -> &[CompactMove] {
+		self.moves.clear();
+		self.moves.extend(generator.take(MoveBuffer::CAPACITY));
+		&self.moves
+	}
+
+	#[inline]
+	pub fn as_slice(&self) -> &[CompactMove] {
+		&self.moves
+	}
+}
+
+impl Board {
+	/// Moves of the side to move, generated lazily without allocating.
+	#[inline]
+	pub fn compact_moves(&self) -> MoveGenerator<'_> {
+		MoveGenerator::new(self, self.whose_move)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::board::tests::random_positions;
+	use im::OrdSet;
+
+	#[test]
+	fn matches_moves_of() {
+		let mut buffer = MoveBuffer::new();
+		for board in random_positions(3, 6, 60) {
+			for color in [Color::White, Color::Black] {
+				let expected: OrdSet<Move> = board.moves_of(color).collect();
+				let actual: OrdSet<Move> = MoveGenerator::new(&board, color).map(|mov| mov.to_move()).collect();
+				assert_eq!(expected, actual);
+				assert_eq!(expected.len(), MoveGenerator::new(&board, color).count());
+				assert_eq!(expected.len(), MoveGenerator::new(&board, color).count_moves());
+				let destinations: OrdSet<Coord> = expected.iter().filter(|mov| matches!(mov, Move::Movement { .. }))
+					.map(|mov| mov.dest()).collect();
+				assert_eq!(destinations.len(), MoveGenerator::new(&board, color).count_destinations());
+			}
+			for mov in buffer.fill(&mut board.compact_moves()) {
+				assert_eq!(Some(*mov), CompactMove::from_move(&mov.to_move()));
+			}
+		}
+	}
+
+	#[test]
+	fn move_buffer_chunks() {
+		let mut buffer = MoveBuffer::new();
+		for board in random_positions(5, 2, 40) {
+			let mut generator = board.compact_moves();
+			let mut chunked = vec![];
+			loop {
+				let chunk = buffer.fill(&mut generator);
+				assert!(chunk.len() <= MoveBuffer::CAPACITY);
+				if chunk.is_empty() {
+					break;
+				}
+				chunked.extend_from_slice(chunk);
+			}
+			assert_eq!(board.compact_moves().collect::<Vec<_>>(), chunked);
+			assert!(buffer.as_slice().is_empty());
+		}
+	}
+
+	#[test]
+	fn packed_moves_round_trip() {
+		for board in random_positions(4, 8, 80) {
+			for mov in board.moves() {
+				let packed = PackedMove::try_from(&mov).unwrap();
+				assert_eq!(packed.color(), mov.color());
+				assert_eq!(mov, packed.to_move(&board));
+			}
+		}
+		assert_eq!(Err(PackMoveError::CoordOutOfRange(Coord(-1,3))),
+			PackedMove::try_from(Move::Placement { color: Color::White, at: Coord(-1,3) }));
+		assert_eq!(Err(PackMoveError::CoordOutOfRange(Coord(16,2))),
+			PackedMove::try_from(Move::movement(Color::Black, Coord(16,2), Coord(15,2))));
+		assert_eq!(Err(PackMoveError::CoordOutOfRange(Coord(20,-3))),
+			PackedMove::try_from(CompactMove::from_move(&Move::Placement { color: Color::White, at: Coord(20,-3) }).unwrap()));
+		assert_eq!(Err(PackMoveError::ConversionNotAdjacent(Coord(0,0))),
+			PackedMove::try_from(Move::Movement { color: Color::White, active: Coord(4,4), pivot: Coord(5,5), conversions: vec![Coord(0,0)] }));
+	}
+
+	#[test]
+	fn conversion_mask_layout() {
+		let black = OrdSet::from(vec![Coord(3,3), Coord(5,3), Coord(7,5), Coord(6,5)]);
+		let white = OrdSet::from(vec![Coord(4,4), Coord(5,4), Coord(4,5)]);
+		let board = Board::from_position(9, Color::Black, white, black);
+		let mov = board.compact_moves()
+			.find(|mov| matches!(mov, CompactMove::Movement { active: Coord(7,5), pivot: Coord(6,5), .. }))
+			.unwrap();
+		assert_eq!(CompactMove::Movement { color: Color::Black, active: Coord(7,5), pivot: Coord(6,5), conversions: 0b1001 }, mov);
+		assert_eq!(vec![Coord(4,4), Coord(5,4)], mov.conversion_coords().collect::<Vec<_>>());
+	}
+}
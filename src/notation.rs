@@ -1,14 +1,16 @@
 use crate::board::*;
 
+use im::OrdSet;
+
 use nom::{
 	IResult,
 	Parser,
 	branch::alt,
 	bytes::complete::{tag},
-	character::complete::{char, one_of, multispace0},
-	combinator::{recognize, map_res, opt},
-	multi::many0,
-	sequence::{pair,preceded},
+	character::complete::{char, digit1, one_of, multispace0, space1},
+	combinator::{recognize, map_res, opt, verify},
+	multi::{many0, many1, separated_list1},
+	sequence::{pair,preceded,terminated},
 };
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
@@ -61,9 +63,37 @@ impl MoveBuilder {
 	}
 }
 
-// pub fn pgn(i: &str) -> IResult<&str, &str> {
-// 	recognize((parse_lines, multispace0, opt(game_result), opt(char('\n')))).parse(i)
-// }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+	pub moves: Vec<Move>,
+	pub winner: Option<Color>
+}
+
+pub fn parse_game(i: &str) -> IResult<&str, GameRecord> {
+	(parse_lines,
+	opt(parse_last_line),
+	opt(preceded(multispace0, game_result))
+	).map(|(lines, last, result)| {
+		let mut moves = Vec::with_capacity(2*lines.len() + 1);
+		for (_, white_move, black_move) in lines {
+			moves.push(white_move);
+			moves.push(black_move);
+		}
+		moves.extend(last);
+		GameRecord { moves, winner: result.map(result_winner) }
+	}).parse(i)
+}
+
+pub fn parse_finished_game(i: &str) -> IResult<&str, GameRecord> {
+	parse_game.map_res(|record| match record.winner {
+		Some(_) => Ok(record),
+		None => Err("game record has no result")
+	}).parse(i)
+}
+
+pub fn parse_games(i: &str) -> IResult<&str, Vec<GameRecord>> {
+	terminated(many0(parse_finished_game), multispace0).parse(i)
+}
 
 pub fn parse_lines(i: &str) -> IResult<&str, Vec<(i32, Move, Move)>> {
 	many0(parse_line).parse(i)
@@ -79,6 +109,12 @@ pub fn parse_line(i: &str) -> IResult<&str, (i32, Move, Move)> {
 	).parse(i)
 }
 
+pub fn parse_last_line(i: &str) -> IResult<&str, Move> {
+	preceded((multispace0, parse_full_move_number, char('.'), multispace0),
+		parse_white_move
+	).parse(i)
+}
+
 pub fn parse_full_move_number(i: &str) -> IResult<&str, i32> {
 	map_res(
 		recognize((
@@ -176,10 +212,144 @@ pub fn game_result(i: &str) -> IResult<&str, &str> {
 	alt((tag("1-0"), tag("0-1"))).parse(i)
 }
 
+pub fn format_coord(coord: Coord) -> String {
+	format!("{}{}", coord.0 + 1, CJK_NUMERALS[coord.1 as usize])
+}
+
+/// Writes `mov` as played on `board`, marking moves that capture or convert with `*`.
+pub fn format_move(board: &Board, mov: &Move) -> String {
+	match mov {
+		Move::Placement { at, .. } => format!("->{}", format_coord(*at)),
+		Move::Movement { color, active, conversions, .. } => {
+			let delta = board.move_delta(mov);
+			let opponent_losses = match color {
+				Color::White => &delta.black_minus,
+				Color::Black => &delta.white_minus
+			};
+			let mut text = format_coord(*active) + &format_coord(mov.dest());
+			if !opponent_losses.is_empty() {
+				text.push('*');
+			}
+			for converted in conversions {
+				text += &format_coord(*converted);
+			}
+			text
+		}
+	}
+}
+
+/// Writes a record from the start position in the format `parse_game` reads.
+pub fn format_game(record: &GameRecord) -> String {
+	let mut text = String::new();
+	let mut board = Board::start_position(9);
+	for (ply, mov) in record.moves.iter().enumerate() {
+		if ply % 2 == 0 {
+			text += &format!("{}. ", ply / 2 + 1);
+		} else {
+			text.push(' ');
+		}
+		text += &format_move(&board, mov);
+		if ply % 2 == 1 {
+			text.push('\n');
+		}
+		board = board.apply(mov);
+	}
+	if record.moves.len() % 2 == 1 {
+		text.push('\n');
+	}
+	match record.winner {
+		Some(Color::White) => text += "1-0\n",
+		Some(Color::Black) => text += "0-1\n",
+		None => ()
+	}
+	text
+}
+
+/// A board on one line: ranks from the top down separated by `/`, each giving its squares
+/// from the left as `W`, `B` or a count of empty squares, then the side to move and the
+/// White and Black reserves. The start position is
+/// `BBBB1WWWW/BBB3WWW/BB5WW/B7W/9/W7B/WW5BB/WWW3BBB/WWWW1BBBB w 0 0`.
+pub fn format_position(board: &Board) -> String {
+	let mut ranks = vec![];
+	for y in (0..board.board_size).rev() {
+		let mut rank = String::new();
+		let mut empties = 0;
+		for x in 0..board.board_size {
+			let square = if board.white.contains(&Coord(x, y)) { 'W' } else if board.black.contains(&Coord(x, y)) { 'B' } else { '.' };
+			if square == '.' {
+				empties += 1;
+				continue;
+			}
+			if empties > 0 {
+				rank += &empties.to_string();
+				empties = 0;
+			}
+			rank.push(square);
+		}
+		if empties > 0 {
+			rank += &empties.to_string();
+		}
+		ranks.push(rank);
+	}
+	let side = match board.whose_move { Color::White => 'w', Color::Black => 'b' };
+	format!("{} {side} {} {}", ranks.join("/"), board.white_reserve, board.black_reserve)
+}
+
+fn parse_position_rank(i: &str) -> IResult<&str, Vec<Option<Color>>> {
+	many1(alt((
+		char('W').map(|_| vec![Some(Color::White)]),
+		char('B').map(|_| vec![Some(Color::Black)]),
+		verify(map_res(digit1, |digits: &str| digits.parse::<usize>()), |empties| (1..=9).contains(empties))
+			.map(|empties| vec![None; empties])
+	))).map(|runs| runs.concat()).parse(i)
+}
+
+/// Reads a board written by `format_position`. The board must be 9x9, and each side's pieces on
+/// the board and in reserve may not number more than `N_PIECES_PER_COLOR`.
+pub fn parse_position(i: &str) -> IResult<&str, Board> {
+	(separated_list1(char('/'), parse_position_rank),
+	preceded(space1, one_of("wb")),
+	preceded(space1, nom::character::complete::i32),
+	preceded(space1, nom::character::complete::i32)
+	).map_opt(|(ranks, side, white_reserve, black_reserve)| {
+		let size = ranks.len();
+		if size != 9 || ranks.iter().any(|rank| rank.len() != size) {
+			return None;
+		}
+		let mut white = OrdSet::new();
+		let mut black = OrdSet::new();
+		for (row, rank) in ranks.iter().enumerate() {
+			for (x, square) in rank.iter().enumerate() {
+				let coord = Coord(x as i32, (size - 1 - row) as i32);
+				match square {
+					Some(Color::White) => { white.insert(coord); },
+					Some(Color::Black) => { black.insert(coord); },
+					None => ()
+				}
+			}
+		}
+		for (pieces, reserve) in [(&white, white_reserve), (&black, black_reserve)] {
+			if reserve < 0 || pieces.len() + reserve as usize > N_PIECES_PER_COLOR {
+				return None;
+			}
+		}
+		let whose_move = if side == 'w' { Color::White } else { Color::Black };
+		Some(Board::from_position_with_reserves(size as i32, whose_move, white, black, white_reserve, black_reserve))
+	}).parse(i)
+}
+
+fn result_winner(result: &str) -> Color {
+	match result {
+		"1-0" => Color::White,
+		"0-1" => Color::Black,
+		_ => panic!("Unknown game result {result:?}")
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use im::OrdSet;
+	use crate::board::tests::random_positions;
 
 	#[test]
 	#[should_panic]
@@ -237,4 +407,77 @@ mod tests {
 		assert_eq!(board.white, expected_white);
 		assert_eq!(board.black, expected_black);
 	}
+
+	#[test]
+	fn format_example_game() {
+		let pgn_example = concat!(
+			"1. 1一5三 1九3五\n",
+			"2. 1二3四 2九2五\n",
+			"3. 3二3六* 3九3七\n",
+			"4. 1四5八 2八4八\n",
+			"5. 1三5九 9一7五\n",
+			"6. 9九7七 9四5六\n",
+			"7. 3六3二 9二7四\n",
+			"8. 4一6五 9三5五*6五\n",
+			"9. 8八6六 ->4六\n",
+			"10. 7八7六 7四3六\n",
+			"11. 3一3三 5六5四\n"
+		);
+		let record = parse_game(pgn_example).unwrap().1;
+		assert_eq!(pgn_example, format_game(&record));
+	}
+
+	#[test]
+	fn parse_game_corpus() {
+		let corpus = concat!(
+			"1. 1一5三 1九3五\n",
+			"2. 1二3四 2九2五\n",
+			"0-1\n",
+			"\n",
+			"1. 1一5三 1九3五\n",
+			"2. ->4六\n",
+			"1-0\n"
+		);
+		let (rest, games) = parse_games(corpus).unwrap();
+		assert_eq!(rest, "");
+		assert_eq!(games.len(), 2);
+		assert_eq!(games[0].moves.len(), 4);
+		assert_eq!(games[0].winner, Some(Color::Black));
+		assert_eq!(games[1].moves.len(), 3);
+		assert_eq!(games[1].moves[2], Move::Placement { color: Color::White, at: Coord(3, 5) });
+		assert_eq!(games[1].winner, Some(Color::White));
+	}
+
+	#[test]
+	fn parse_game_without_result() {
+		let (_, game) = parse_game("1. 1一5三 1九3五\n").unwrap();
+		assert_eq!(game.moves.len(), 2);
+		assert_eq!(game.winner, None);
+		assert!(parse_finished_game("1. 1一5三 1九3五\n").is_err());
+	}
+
+	#[test]
+	fn position_round_trip() {
+		let start = Board::start_position(9);
+		let text = format_position(&start);
+		assert_eq!("BBBB1WWWW/BBB3WWW/BB5WW/B7W/9/W7B/WW5BB/WWW3BBB/WWWW1BBBB w 0 0", text);
+		assert_eq!(start, parse_position(&text).unwrap().1);
+
+		for board in random_positions(8, 1, 40) {
+			let parsed = parse_position(&format_position(&board)).unwrap().1;
+			assert_eq!(board, parsed);
+			assert_eq!(board.whose_move, parsed.whose_move);
+			assert_eq!(board.zobrist_hash, parsed.zobrist_hash);
+		}
+		assert!(parse_position("W7B/9 w 0 0").is_err());
+		assert!(parse_position("9/9/9/9/9/9/9/9/9 x 0 0").is_err());
+		assert!(parse_position("9/9/9/9/9/9/9/9/9 w 99 0").is_err());
+		assert!(parse_position("9/9/9/9/9/9/9/9/9 w -1 0").is_err());
+		assert!(parse_position("WWWWWWWWW/WWWWWWWWW/WWW6/9/9/9/9/9/9 w 0 0").is_err());
+		assert!(parse_position("W8/9/9/9/9/9/9/9/9 w 20 0").is_err());
+		assert!(parse_position("9/9/9/9/9/9/9/9/9 w 20 20").is_ok());
+		assert!(parse_position("10/10/10/10/10/10/10/10/10/10 w 0 0").is_err());
+		assert!(parse_position("99999999999999/9/9/9/9/9/9/9/9 w 0 0").is_err());
+		assert!(parse_position("0W8/9/9/9/9/9/9/9/9 w 0 0").is_err());
+	}
 }
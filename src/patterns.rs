@@ -0,0 +1,225 @@
+use crate::ai::*;
+use crate::board::*;
+use crate::movegen::NEIGHBOR_OFFSETS;
+use crate::notation::*;
+use crate::tuning::*;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The centre and each of its eight neighbours is empty, own, opponent or off the board.
+pub const N_PATTERNS: usize = 1 << 18;
+
+const EMPTY: usize = 0;
+const OWN: usize = 1;
+const OPPONENT: usize = 2;
+const OFF_BOARD: usize = 3;
+
+/// First line of a pattern file. Version 1 files indexed only the neighbours of a piece.
+const PATTERNS_HEADER: &str = "# 3x3 pattern weights v2: index weight";
+
+fn square_state(board: &Board, coord: Coord, color: Color) -> usize {
+	if !board.in_bounds(coord) {
+		OFF_BOARD
+	} else if board.pieces_of(color).contains(&coord) {
+		OWN
+	} else if board.pieces_of(color.opponent()).contains(&coord) {
+		OPPONENT
+	} else {
+		EMPTY
+	}
+}
+
+/// Index of the 3x3 window centred on `coord` as seen by `color`, with two bits per neighbour
+/// in `NEIGHBOR_OFFSETS` order, the first neighbour in the lowest bits, and the centre in the
+/// highest two bits.
+pub fn pattern_index(board: &Board, coord: Coord, color: Color) -> usize {
+	let neighbors: usize = NEIGHBOR_OFFSETS.iter().enumerate()
+		.map(|(i, (dx, dy))| square_state(board, Coord(coord.0 + dx, coord.1 + dy), color) << (2 * i))
+		.sum();
+	neighbors | square_state(board, coord, color) << 16
+}
+
+/// Sums a learned weight for the window around every square, including empty ones, seen from
+/// White's side, less the weight of the same window seen from Black's side, so that swapping
+/// the colors negates the score. Weights are in centi-units.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PatternHeuristic {
+	pub weights: Vec<Valuation>
+}
+
+impl Default for PatternHeuristic {
+	fn default() -> Self {
+		PatternHeuristic { weights: vec![0; N_PATTERNS] }
+	}
+}
+
+impl PatternHeuristic {
+	/// How often each pattern occurs for White minus for Black, omitting those that cancel.
+	pub fn features(board: &Board) -> Vec<(usize, f64)> {
+		let mut counts: HashMap<usize, f64> = HashMap::new();
+		for coord in board.all_coords() {
+			*counts.entry(pattern_index(board, coord, Color::White)).or_default() += 1.0;
+			*counts.entry(pattern_index(board, coord, Color::Black)).or_default() -= 1.0;
+		}
+		let mut features: Vec<_> = counts.into_iter().filter(|(_, count)| *count != 0.0).collect();
+		features.sort_by_key(|(index, _)| *index);
+		features
+	}
+
+	/// Writes the nonzero weights as `index weight` lines.
+	pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let mut text = format!("{PATTERNS_HEADER}\n");
+		for (index, weight) in self.weights.iter().enumerate().filter(|(_, weight)| **weight != 0) {
+			text += &format!("{index} {weight}\n");
+		}
+		fs::write(path, text)
+	}
+
+	/// Reads a file written by `save`. Patterns not listed have weight 0.
+	pub fn load(path: impl AsRef<Path>) -> io::Result<PatternHeuristic> {
+		let mut heuristic = PatternHeuristic::default();
+		let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Bad pattern weight line {line:?}"));
+		let text = fs::read_to_string(path)?;
+		let mut lines = text.lines().map(str::trim);
+		if lines.next() != Some(PATTERNS_HEADER) {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a version 2 pattern file"));
+		}
+		for line in lines {
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let mut fields = line.split_whitespace();
+			let (Some(index), Some(weight), None) = (fields.next(), fields.next(), fields.next()) else {
+				return Err(invalid(line));
+			};
+			let index: usize = index.parse().map_err(|_| invalid(line))?;
+			*heuristic.weights.get_mut(index).ok_or_else(|| invalid(line))? = weight.parse().map_err(|_| invalid(line))?;
+		}
+		Ok(heuristic)
+	}
+}
+
+impl Heuristic for PatternHeuristic {
+	#[inline]
+	fn heuristic(&self, board: &Board) -> Valuation {
+		let mut sum: i64 = 0;
+		for coord in board.all_coords() {
+			sum += self.weights[pattern_index(board, coord, Color::White)] as i64;
+			sum -= self.weights[pattern_index(board, coord, Color::Black)] as i64;
+		}
+		clamp_heuristic(sum)
+	}
+}
+
+/// Fits pattern weights to game results with a `LogisticFitter`, using the pattern counts as
+/// sparse features and an L2 penalty for the many rarely seen patterns.
+pub struct PatternTrainer {
+	pub learning_rate: f64,
+	pub iterations: usize,
+	pub skip_plies: usize,
+	pub regularization: f64,
+	/// Centi-units per unit of the fitted logistic evaluation
+	pub scale: f64
+}
+
+impl Default for PatternTrainer {
+	fn default() -> Self {
+		PatternTrainer { learning_rate: 1.0, iterations: 500, skip_plies: 4, regularization: 1e-4, scale: 100.0 }
+	}
+}
+
+impl PatternTrainer {
+	/// Pattern counts of every position `for_each_decided_position` visits.
+	pub fn extract_positions(&self, records: &[GameRecord]) -> Vec<SparsePosition> {
+		let mut positions = vec![];
+		for_each_decided_position(records, self.skip_plies, |board, result| {
+			positions.push(SparsePosition { features: PatternHeuristic::features(board), result });
+		});
+		positions
+	}
+
+	pub fn fit(&self, positions: &[SparsePosition]) -> Vec<f64> {
+		LogisticFitter { learning_rate: self.learning_rate, iterations: self.iterations, regularization: self.regularization }
+			.fit(N_PATTERNS, positions)
+	}
+
+	pub fn to_heuristic(&self, weights: &[f64]) -> PatternHeuristic {
+		PatternHeuristic { weights: weights.iter().map(|weight| clamp_heuristic((weight * self.scale).round() as i64)).collect() }
+	}
+
+	pub fn train(&self, records: &[GameRecord]) -> PatternHeuristic {
+		self.to_heuristic(&self.fit(&self.extract_positions(records)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use im::OrdSet;
+
+	#[test]
+	fn pattern_indices() {
+		let white = OrdSet::from(vec![Coord(0,0), Coord(1,1)]);
+		let black = OrdSet::from(vec![Coord(1,0), Coord(8,8)]);
+		let board = Board::from_position(9, Color::White, white.clone(), black.clone());
+		// White in the centre, off the board to the left and below, Black at (1,0), White at (1,1)
+		assert_eq!(3*(1 + 4 + 16 + 64) + 3*1024 + (OPPONENT << 12) + (OWN << 14) + (OWN << 16), pattern_index(&board, Coord(0,0), Color::White));
+		assert_eq!(3*(1 + 4 + 16 + 64) + 3*1024 + (OWN << 12) + (OPPONENT << 14) + (OPPONENT << 16), pattern_index(&board, Coord(0,0), Color::Black));
+		assert_eq!(EMPTY, pattern_index(&board, Coord(4,4), Color::White) >> 16);
+
+		let mut heuristic = PatternHeuristic::default();
+		heuristic.weights[pattern_index(&board, Coord(0,0), Color::White)] = 7;
+		heuristic.weights[pattern_index(&board, Coord(8,8), Color::Black)] = 2;
+		assert_eq!(7 - 2, heuristic.heuristic(&board));
+		let swapped = Board::from_position(9, Color::Black, black, white);
+		assert_eq!(2 - 7, heuristic.heuristic(&swapped));
+
+		// An empty square next to both colors is a shape of its own
+		heuristic.weights[pattern_index(&board, Coord(2,0), Color::White)] = 3;
+		assert_eq!(7 - 2 + 3, heuristic.heuristic(&board));
+		assert_eq!(2 - 7 - 3, heuristic.heuristic(&swapped));
+		let features = PatternHeuristic::features(&board);
+		assert!(features.contains(&(pattern_index(&board, Coord(2,0), Color::White), 1.0)));
+		let dot: f64 = features.iter().map(|(index, count)| heuristic.weights[*index] as f64 * count).sum();
+		assert_eq!(heuristic.heuristic(&board) as f64, dot);
+	}
+
+	#[test]
+	fn weights_round_trip() {
+		let mut heuristic = PatternHeuristic::default();
+		heuristic.weights[3327] = -40;
+		heuristic.weights[N_PATTERNS - 1] = 12;
+		let path = std::env::temp_dir().join(format!("quorum-patterns-{}.txt", std::process::id()));
+		heuristic.save(&path).unwrap();
+		assert_eq!(heuristic, PatternHeuristic::load(&path).unwrap());
+		fs::write(&path, format!("{PATTERNS_HEADER}\n{} 1\n", N_PATTERNS)).unwrap();
+		assert!(PatternHeuristic::load(&path).is_err());
+		fs::write(&path, "# 3x3 pattern weights: index weight\n3327 -40\n").unwrap();
+		assert!(PatternHeuristic::load(&path).is_err());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn fit_learns_winning_shape() {
+		let positions: Vec<_> = (0..100).map(|i| {
+			let winning = i % 2 == 0;
+			let shape = if winning { (10, 1.0) } else { (10, -1.0) };
+			let noise = (20 + i % 3, 1.0);
+			SparsePosition { features: vec![shape, noise], result: if winning { 1.0 } else { 0.0 } }
+		}).collect();
+		let trainer = PatternTrainer { iterations: 200, ..PatternTrainer::default() };
+		let weights = trainer.fit(&positions);
+		assert!(weights[10] > 0.0);
+		assert!(weights[10] > 5.0 * weights[20].abs());
+		assert!(positions.iter().all(|position| (position.eval(&weights) > 0.0) == (position.result > 0.5)));
+		assert!(trainer.to_heuristic(&weights).weights[10] > 0);
+
+		let records = parse_games("1. 1一5三 1九3五\n2. 1二3四 2九2五\n0-1\n").unwrap().1;
+		let extracted = PatternTrainer { skip_plies: 0, ..PatternTrainer::default() }.extract_positions(&records);
+		assert_eq!(4, extracted.len());
+		assert!(extracted.iter().all(|position| position.result == 0.0));
+	}
+}
@@ -0,0 +1,126 @@
+use crate::board::*;
+use crate::movegen::MoveBuffer;
+
+/// Number of move sequences of length `depth` from `board`. Won positions are terminal,
+/// so sequences stop early there and are not counted. Moves come from `MoveGenerator`,
+/// while `divide` uses `Board::moves`, so comparing the two checks one against the other.
+pub fn perft(board: &Board, depth: u32) -> u64 {
+	if depth == 0 {
+		return 1;
+	}
+	if board.winner().is_some() {
+		return 0;
+	}
+	let mut generator = board.compact_moves();
+	let mut buffer = MoveBuffer::new();
+	let mut count = 0;
+	loop {
+		let moves = buffer.fill(&mut generator);
+		if moves.is_empty() {
+			return count;
+		}
+		count += match depth {
+			1 => moves.len() as u64,
+			_ => moves.iter().map(|mov| perft(&board.apply(&mov.to_move()), depth - 1)).sum()
+		};
+	}
+}
+
+/// Per-move breakdown of `perft`, sorted by move, for narrowing down generator differences.
+pub fn divide(board: &Board, depth: u32) -> Vec<(Move, u64)> {
+	assert!(depth >= 1);
+	let mut counts: Vec<_> = board.moves()
+		.map(|mov| {
+			let count = perft(&board.apply(&mov), depth - 1);
+			(mov, count)
+		})
+		.collect();
+	counts.sort();
+	counts
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::notation::*;
+	use im::OrdSet;
+
+	const REFERENCE_COUNTS: [(&str, [u64; 4]); 5] = [
+		("start", [34, 1124, 40180, 1427428]),
+		("placement", [78, 5976, 333069, 18302345]),
+		("midgame", [95, 5997, 457975, 30102142]),
+		("capture", [106, 7562, 773818, 56197758]),
+		("sparse", [5, 390, 1463, 11253])
+	];
+
+	// Larger counts take minutes in a debug build and are left to `perft_reference_deep`,
+	// which runs in release builds, as the perft-deep CI job does; "sparse" is small enough
+	// to check to full depth here
+	const ROUTINE_MAX_NODES: u64 = 100_000;
+
+	fn reference_position(name: &str) -> Board {
+		match name {
+			"start" => Board::start_position(9),
+			"placement" => {
+				let start = Board::start_position(9);
+				Board::from_position_with_reserves(9, Color::White, start.white.without(&Coord(3,0)), start.black.without(&Coord(5,0)), 1, 1)
+			},
+			"midgame" => {
+				let record = parse_game(concat!(
+					"1. 1一5三 1九3五\n",
+					"2. 1二3四 2九2五\n",
+					"3. 3二3六* 3九3七\n",
+					"4. 1四5八 2八4八\n",
+					"5. 1三5九 9一7五\n",
+					"6. 9九7七 9四5六\n",
+					"7. 3六3二 9二7四\n",
+					"8. 4一6五 9三5五*6五\n",
+					"9. 8八6六 ->4六\n",
+					"10. 7八7六 7四3六\n"
+				)).unwrap().1;
+				record.moves.iter().fold(Board::start_position(9), |board, mov| board.apply(mov))
+			},
+			"capture" => {
+				let black = OrdSet::from(vec![Coord(1,1), Coord(1,2), Coord(1,3), Coord(2,1), Coord(3,1), Coord(3,4), Coord(3,5), Coord(4,2), Coord(4,3)]);
+				let white = OrdSet::from(vec![Coord(2,2), Coord(2,3), Coord(3,2), Coord(4,1)]);
+				Board::from_position(9, Color::Black, white, black)
+			},
+			"sparse" => {
+				let white = OrdSet::from(vec![Coord(2,2), Coord(3,3), Coord(5,2)]);
+				let black = OrdSet::from(vec![Coord(3,2), Coord(4,3), Coord(6,6)]);
+				Board::from_position_with_reserves(9, Color::White, white, black, 0, 1)
+			},
+			_ => panic!("Unknown reference position {name}")
+		}
+	}
+
+	fn check_reference_counts(max_nodes: u64) {
+		for (name, counts) in REFERENCE_COUNTS {
+			let board = reference_position(name);
+			for (depth, expected) in (1..).zip(counts) {
+				if expected <= max_nodes {
+					assert_eq!(expected, perft(&board, depth), "perft({name}, {depth})");
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn perft_reference() {
+		check_reference_counts(ROUTINE_MAX_NODES);
+	}
+
+	#[test]
+	#[cfg_attr(debug_assertions, ignore)]
+	fn perft_reference_deep() {
+		check_reference_counts(u64::MAX);
+	}
+
+	#[test]
+	fn divide_sums_to_perft() {
+		let board = reference_position("placement");
+		let counts = divide(&board, 2);
+		assert_eq!(78, counts.len());
+		assert_eq!(perft(&board, 2), counts.iter().map(|(_, count)| count).sum());
+	}
+}
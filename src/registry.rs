@@ -0,0 +1,211 @@
+use crate::ai::*;
+
+use nom::{
+	IResult,
+	Parser,
+	branch::alt,
+	bytes::complete::tag,
+	character::complete::{alpha1, alphanumeric1, char, multispace0, one_of},
+	combinator::{all_consuming, opt, recognize},
+	multi::{many0, separated_list0},
+	number::complete::double,
+	sequence::{delimited, pair, separated_pair, terminated},
+};
+use std::fmt;
+
+/// The evaluator used when none is given on the command line.
+pub const DEFAULT_HEURISTIC: &str = "1000*centroid(power=2) + 1*piece_count + 5*connected_components";
+
+pub struct Parameter {
+	pub name: &'static str,
+	pub default: f64,
+	/// Whether only whole numbers are accepted
+	pub integer: bool
+}
+
+/// A built-in heuristic that can be named in a config. `build` receives one value per
+/// parameter, in the order of `parameters`.
+pub struct HeuristicInfo {
+	pub name: &'static str,
+	pub description: &'static str,
+	pub parameters: &'static [Parameter],
+	pub build: fn(&[f64]) -> Box<dyn Heuristic>
+}
+
+const fn integer(name: &'static str, default: f64) -> Parameter {
+	Parameter { name, default, integer: true }
+}
+
+pub const REGISTRY: &[HeuristicInfo] = &[
+	HeuristicInfo { name: "piece_count", description: "pieces on the board",
+		parameters: &[], build: |_| Box::new(PieceCountHeuristic {}) },
+	HeuristicInfo { name: "legal_moves", description: "number of legal moves",
+		parameters: &[], build: |_| Box::new(LegalMovesHeuristic {}) },
+	HeuristicInfo { name: "reachable_destinations", description: "empty squares reachable by a movement",
+		parameters: &[], build: |_| Box::new(ReachableDestinationsHeuristic {}) },
+	HeuristicInfo { name: "territory", description: "empty squares nearer one's own pieces",
+		parameters: &[], build: |_| Box::new(TerritoryHeuristic {}) },
+	HeuristicInfo { name: "centroid", description: "spread of pieces around their centroid",
+		parameters: &[Parameter { name: "power", default: 2.0, integer: false }],
+		build: |params| Box::new(CentroidDistanceHeuristic { power: params[0] as f32 }) },
+	HeuristicInfo { name: "connected_components", description: "number of groups",
+		parameters: &[], build: |_| Box::new(ConnectedComponentsHeuristic {}) },
+	HeuristicInfo { name: "nth_smallest_string", description: "size of the nth smallest group",
+		parameters: &[integer("n", 1.0)],
+		build: |params| Box::new(NthSmallestStringHeuristic { n: params[0] as usize }) },
+	HeuristicInfo { name: "connection_distance", description: "moves needed to join all groups",
+		parameters: &[], build: |_| Box::new(ConnectionDistanceHeuristic {}) },
+	HeuristicInfo { name: "few_liberties", description: "pieces with few empty neighbours",
+		parameters: &[integer("max_liberties", 1.0)],
+		build: |params| Box::new(FewLibertiesHeuristic { max_liberties: params[0] as usize }) },
+	HeuristicInfo { name: "capturable", description: "pieces the opponent can capture next move",
+		parameters: &[], build: |_| Box::new(CapturableHeuristic {}) },
+	HeuristicInfo { name: "convertible", description: "pieces the opponent can convert next move",
+		parameters: &[], build: |_| Box::new(ConvertibleHeuristic {}) },
+	HeuristicInfo { name: "material", description: "pieces on the board and in reserve",
+		parameters: &[integer("on_board", 2.0), integer("in_reserve", 1.0)],
+		build: |params| Box::new(MaterialHeuristic { on_board: params[0] as Valuation, in_reserve: params[1] as Valuation }) },
+	HeuristicInfo { name: "reserve", description: "reserve pieces with a use",
+		parameters: &[integer("drop_weight", 1.0), integer("conversion_weight", 1.0)],
+		build: |params| Box::new(ReserveHeuristic { drop_weight: params[0] as Valuation, conversion_weight: params[1] as Valuation }) },
+];
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConfigError {
+	Syntax(String),
+	UnknownHeuristic(String),
+	UnknownParameter { heuristic: String, parameter: String },
+	DuplicateParameter { heuristic: String, parameter: String },
+	NotAnInteger { heuristic: String, parameter: String, value: f64 }
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Syntax(rest) => write!(f, "Could not parse heuristic config at {rest:?}"),
+			ConfigError::UnknownHeuristic(name) => write!(f, "No heuristic named {name}"),
+			ConfigError::UnknownParameter { heuristic, parameter } => write!(f, "{heuristic} has no parameter {parameter}"),
+			ConfigError::DuplicateParameter { heuristic, parameter } => write!(f, "{parameter} given twice for {heuristic}"),
+			ConfigError::NotAnInteger { heuristic, parameter, value } => write!(f, "{parameter} of {heuristic} must be a whole number, not {value}")
+		}
+	}
+}
+
+pub fn lookup(name: &str) -> Option<&'static HeuristicInfo> {
+	REGISTRY.iter().find(|info| info.name == name)
+}
+
+/// One `weight*name(parameter=value, ...)` term of a config, before lookup.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TermSpec<'a> {
+	pub weight: Valuation,
+	pub name: &'a str,
+	pub parameters: Vec<(&'a str, f64)>
+}
+
+fn ws<'a, O, P>(parser: P) -> impl Parser<&'a str, Output=O, Error=nom::error::Error<&'a str>>
+where P: Parser<&'a str, Output=O, Error=nom::error::Error<&'a str>> {
+	delimited(multispace0, parser, multispace0)
+}
+
+fn parse_identifier(i: &str) -> IResult<&str, &str> {
+	recognize(pair(alpha1, many0(alt((alphanumeric1, tag("_")))))).parse(i)
+}
+
+pub fn parse_term(i: &str) -> IResult<&str, TermSpec<'_>> {
+	(opt(terminated(nom::character::complete::i32, ws(char('*')))),
+	parse_identifier,
+	opt(delimited(ws(char('(')),
+		separated_list0(ws(char(',')), separated_pair(parse_identifier, ws(char('=')), double)),
+		ws(char(')'))))
+	).map(|(weight, name, parameters)| TermSpec { weight: weight.unwrap_or(1), name, parameters: parameters.unwrap_or_default() })
+	.parse(i)
+}
+
+/// Terms separated by `+` or `-`, where `-` negates the weight of the term after it.
+pub fn parse_terms(i: &str) -> IResult<&str, Vec<TermSpec<'_>>> {
+	all_consuming((ws(parse_term), many0(pair(one_of("+-"), ws(parse_term)))))
+		.map(|(first, rest)| {
+			let mut terms = vec![first];
+			terms.extend(rest.into_iter().map(|(sign, term)| if sign == '-' { TermSpec { weight: -term.weight, ..term } } else { term }));
+			terms
+		})
+		.parse(i)
+}
+
+pub fn build_term(term: &TermSpec) -> Result<Box<dyn Heuristic>, ConfigError> {
+	let info = lookup(term.name).ok_or_else(|| ConfigError::UnknownHeuristic(term.name.to_string()))?;
+	let mut values: Vec<Option<f64>> = vec![None; info.parameters.len()];
+	for (name, value) in term.parameters.iter() {
+		let error_fields = || (info.name.to_string(), name.to_string());
+		let index = info.parameters.iter().position(|parameter| parameter.name == *name)
+			.ok_or_else(|| { let (heuristic, parameter) = error_fields(); ConfigError::UnknownParameter { heuristic, parameter } })?;
+		if values[index].is_some() {
+			let (heuristic, parameter) = error_fields();
+			return Err(ConfigError::DuplicateParameter { heuristic, parameter });
+		}
+		if info.parameters[index].integer && value.fract() != 0.0 {
+			let (heuristic, parameter) = error_fields();
+			return Err(ConfigError::NotAnInteger { heuristic, parameter, value: *value });
+		}
+		values[index] = Some(*value);
+	}
+	let values: Vec<f64> = values.into_iter().zip(info.parameters)
+		.map(|(value, parameter)| value.unwrap_or(parameter.default))
+		.collect();
+	Ok((info.build)(&values))
+}
+
+/// Builds the weighted sum described by a config such as `5*connected_components + 1*centroid(power=2)`.
+pub fn parse_heuristic(config: &str) -> Result<LinearCombinationHeuristic, ConfigError> {
+	let (_, terms) = parse_terms(config).map_err(|err| ConfigError::Syntax(match err {
+		nom::Err::Error(err) | nom::Err::Failure(err) => err.input.to_string(),
+		nom::Err::Incomplete(_) => String::new()
+	}))?;
+	let terms = terms.iter()
+		.map(|term| Ok((term.weight, build_term(term)?)))
+		.collect::<Result<_, ConfigError>>()?;
+	Ok(LinearCombinationHeuristic { terms })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::board::tests::random_positions;
+
+	#[test]
+	fn parse_configs() {
+		let handmade = LinearCombinationHeuristic { terms: vec![
+			(1000, Box::new(CentroidDistanceHeuristic { power: 2.0 })),
+			(1, Box::new(PieceCountHeuristic {})),
+			(5, Box::new(ConnectedComponentsHeuristic {}))
+		]};
+		let parsed = parse_heuristic(DEFAULT_HEURISTIC).unwrap();
+		let spaced = parse_heuristic("1000 * centroid( power = 2 )+piece_count  +  5 * connected_components").unwrap();
+		let positions = random_positions(7, 1, 30);
+		for board in &positions {
+			assert_eq!(handmade.heuristic(board), parsed.heuristic(board));
+			assert_eq!(handmade.heuristic(board), spaced.heuristic(board));
+		}
+
+		let explained = parse_heuristic("piece_count + nth_smallest_string(n=2)").unwrap();
+		assert_eq!(vec!["PieceCountHeuristic".to_string(), "NthSmallestStringHeuristic(n=2)".to_string()],
+			explained.explain(positions.last().unwrap()).into_iter().map(|term| term.name).collect::<Vec<_>>());
+
+		let negated = parse_heuristic("territory - 2*material(in_reserve=0, on_board=1)").unwrap();
+		assert_eq!(vec![1, -2], negated.terms.iter().map(|(weight, _)| *weight).collect::<Vec<_>>());
+		for info in REGISTRY {
+			assert!(parse_heuristic(info.name).is_ok());
+		}
+	}
+
+	#[test]
+	fn config_errors() {
+		assert_eq!(Err(ConfigError::UnknownHeuristic("mystery".to_string())), parse_heuristic("1*mystery").map(|_| ()));
+		assert_eq!(Err(ConfigError::Syntax("* territory".to_string())), parse_heuristic("piece_count * territory").map(|_| ()));
+		assert!(matches!(parse_heuristic("centroid(pow=2)"), Err(ConfigError::UnknownParameter { .. })));
+		assert!(matches!(parse_heuristic("centroid(power=2, power=3)"), Err(ConfigError::DuplicateParameter { .. })));
+		assert!(matches!(parse_heuristic("nth_smallest_string(n=1.5)"), Err(ConfigError::NotAnInteger { .. })));
+		assert!(parse_heuristic("").is_err());
+	}
+}
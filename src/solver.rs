@@ -0,0 +1,242 @@
+use crate::ai::*;
+use crate::board::*;
+
+use std::collections::HashMap;
+
+/// Depth-first prover for short forced wins. Results are cached by Zobrist hash and remaining
+/// depth, so a solver should only be reused for positions descended from the same root.
+#[derive(Default)]
+pub struct Solver {
+	proven: HashMap<(u64, u32), Option<Vec<Move>>>,
+	pub nodes: u64
+}
+
+impl Solver {
+	pub fn new() -> Solver {
+		Solver { proven: HashMap::new(), nodes: 0 }
+	}
+
+	/// Returns a line winning for the side to move within `max_plies` plies (counting both
+	/// sides' moves), trying shorter wins first, or `None` if there is no forced win.
+	pub fn solve(&mut self, board: &Board, max_plies: u32) -> Option<Vec<Move>> {
+		(1..=max_plies).step_by(2).find_map(|plies| self.forced_win(board, plies))
+	}
+
+	/// The main line of a forced win in at most `plies` plies. Along the line the defender
+	/// plays whichever reply delays the win the longest. A defender left without legal replies
+	/// has not lost, since games stopped that way are scored as draws, so such moves prove nothing.
+	pub fn forced_win(&mut self, board: &Board, plies: u32) -> Option<Vec<Move>> {
+		if plies == 0 {
+			return None;
+		}
+		if let Some(result) = self.proven.get(&(board.zobrist_hash, plies)) {
+			return result.clone();
+		}
+		self.nodes += 1;
+		let mover = board.whose_move;
+		let mut result = None;
+		'moves: for mov in board.moves() {
+			let child = board.apply(&mov);
+			match child.winner() {
+				Some(winner) if winner == mover => {
+					result = Some(vec![mov]);
+					break;
+				},
+				Some(_) => continue,
+				None if plies < 3 => continue,
+				None => ()
+			}
+			let mut longest_defence: Option<Vec<Move>> = None;
+			let mut replies = 0;
+			for reply in child.moves() {
+				replies += 1;
+				let grandchild = child.apply(&reply);
+				let continuation = match grandchild.winner() {
+					Some(winner) if winner == mover => vec![],
+					Some(_) => continue 'moves,
+					None => match self.forced_win(&grandchild, plies - 2) {
+						Some(line) => line,
+						None => continue 'moves
+					}
+				};
+				if longest_defence.as_ref().is_none_or(|line| line.len() < continuation.len() + 1) {
+					let mut line = vec![reply];
+					line.extend(continuation);
+					longest_defence = Some(line);
+				}
+			}
+			let Some(defence) = longest_defence.filter(|_| replies > 0) else {
+				continue;
+			};
+			let mut line = vec![mov];
+			line.extend(defence);
+			result = Some(line);
+			break;
+		}
+		self.proven.insert((board.zobrist_hash, plies), result.clone());
+		result
+	}
+}
+
+/// Number of pieces of `color` outside its largest connected group.
+pub fn stragglers(board: &Board, color: Color) -> usize {
+	let largest = board.components_of(color).sizes().max().unwrap_or(0);
+	board.pieces_of(color).len() - largest
+}
+
+/// When and how deep `SolverExtendedHeuristic` looks for forced wins.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SolverSettings {
+	pub max_stragglers: usize,
+	pub plies: u32
+}
+
+impl Default for SolverSettings {
+	fn default() -> Self {
+		SolverSettings { max_stragglers: 1, plies: 1 }
+	}
+}
+
+impl SolverSettings {
+	/// Reads `,`-separated `max_stragglers=N` and `plies=N` settings; omitted ones keep their defaults.
+	pub fn parse(settings: &str) -> Result<SolverSettings, String> {
+		let mut solver = SolverSettings::default();
+		for setting in settings.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+			let (key, value) = setting.split_once('=').ok_or_else(|| format!("Expected key=value in solver setting {setting:?}"))?;
+			let number = || value.trim().parse().map_err(|_| format!("{} must be a whole number, not {value:?}", key.trim()));
+			match key.trim() {
+				"max_stragglers" => solver.max_stragglers = number()? as usize,
+				"plies" => solver.plies = number()?,
+				_ => return Err(format!("Unknown solver setting {key:?}"))
+			}
+		}
+		Ok(solver)
+	}
+
+	pub fn extend<H: Heuristic>(self, inner: H) -> SolverExtendedHeuristic<H> {
+		SolverExtendedHeuristic { inner, settings: self }
+	}
+}
+
+/// Runs the solver at leaves where the side to move has at most `max_stragglers` pieces
+/// outside its main group, and falls back to `inner` if no forced win is found. A proven win
+/// is `Valuation::MAX` or `MIN`, beyond any heuristic, so this must wrap the whole evaluation
+/// rather than be one weighted term of it.
+pub struct SolverExtendedHeuristic<H: Heuristic> {
+	pub inner: H,
+	pub settings: SolverSettings
+}
+
+impl<H: Heuristic> SolverExtendedHeuristic<H> {
+	fn proven_win(&self, board: &Board) -> bool {
+		board.winner().is_none() && stragglers(board, board.whose_move) <= self.settings.max_stragglers
+			&& Solver::new().solve(board, self.settings.plies).is_some()
+	}
+}
+
+impl<H: Heuristic> Heuristic for SolverExtendedHeuristic<H> {
+	fn heuristic(&self, board: &Board) -> Valuation {
+		if self.proven_win(board) {
+			return match board.whose_move {
+				Color::White => Valuation::MAX,
+				Color::Black => Valuation::MIN
+			};
+		}
+		self.inner.heuristic(board)
+	}
+
+	fn name(&self) -> String {
+		format!("SolverExtendedHeuristic(max_stragglers={}, plies={})", self.settings.max_stragglers, self.settings.plies)
+	}
+
+	/// The solver's verdict alone when it proves a win, otherwise the terms of `inner`.
+	fn explain(&self, board: &Board) -> Vec<TermBreakdown> {
+		if self.proven_win(board) {
+			let value = self.heuristic(board);
+			return vec![TermBreakdown { name: self.name(), value, weight: 1, contribution: value }];
+		}
+		self.inner.explain(board)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use im::OrdSet;
+
+	#[test]
+	fn solves_connection_in_one() {
+		let white = OrdSet::from(vec![Coord(0,0), Coord(1,0), Coord(3,0)]);
+		let black = OrdSet::from(vec![Coord(8,8), Coord(6,6), Coord(8,6)]);
+		let board = Board::from_position(9, Color::White, white, black);
+		assert_eq!(1, stragglers(&board, Color::White));
+
+		let line = Solver::new().solve(&board, 1).expect("No win found");
+		assert_eq!(1, line.len());
+		assert_eq!(Some(Color::White), board.apply(&line[0]).winner());
+	}
+
+	fn sparse_position(white: Vec<Coord>, black: Vec<Coord>) -> Board {
+		Board::from_position_with_reserves(9, Color::White, OrdSet::from(white), OrdSet::from(black), 0, 0)
+	}
+
+	/// Whether the mover could win next turn if the opponent passed after `mov`.
+	fn threatens(board: &Board, mov: &Move) -> bool {
+		let mut child = board.apply(mov);
+		child.whose_move = board.whose_move;
+		child.zobrist_hash = child.compute_zobrist_hash();
+		let threat = child.winner().is_none() && child.moves().any(|next| child.apply(&next).winner() == Some(board.whose_move));
+		threat
+	}
+
+	#[test]
+	fn solves_connection_in_three() {
+		let board = sparse_position(vec![Coord(6,3), Coord(4,2), Coord(3,2)], vec![Coord(2,6), Coord(4,4), Coord(3,4)]);
+		assert_eq!(None, Solver::new().solve(&board, 1));
+		let line = Solver::new().solve(&board, 3).expect("No win found");
+		assert_eq!(3, line.len());
+		let threat = board.apply(&line[0]);
+		for reply in threat.moves() {
+			let after = threat.apply(&reply);
+			assert!(after.moves().any(|mov| after.apply(&mov).winner() == Some(Color::White)), "{reply:?} defends");
+		}
+		let end = line.iter().fold(board, |board, mov| board.apply(mov));
+		assert_eq!(Some(Color::White), end.winner());
+	}
+
+	#[test]
+	fn every_threat_refuted() {
+		let board = sparse_position(vec![Coord(6,5), Coord(5,2), Coord(6,4)], vec![Coord(3,4), Coord(4,6), Coord(3,5)]);
+		assert!(board.moves().filter(|mov| threatens(&board, mov)).count() >= 2);
+		assert_eq!(None, Solver::new().solve(&board, 3));
+
+		let heuristic = SolverSettings { max_stragglers: 3, plies: 3 }.extend(PieceCountHeuristic {});
+		assert_eq!(PieceCountHeuristic {}.heuristic(&board), heuristic.heuristic(&board));
+		let winning = sparse_position(vec![Coord(6,3), Coord(4,2), Coord(3,2)], vec![Coord(2,6), Coord(4,4), Coord(3,4)]);
+		assert_eq!(Valuation::MAX, heuristic.heuristic(&winning));
+	}
+
+	#[test]
+	fn proven_wins_survive_the_configured_evaluation() {
+		let white = OrdSet::from(vec![Coord(0,0), Coord(1,0), Coord(3,0)]);
+		let black = OrdSet::from(vec![Coord(8,8), Coord(6,6), Coord(8,6)]);
+		let board = Board::from_position(9, Color::White, white, black);
+		let configured = crate::registry::parse_heuristic("1000*territory - 50*piece_count").unwrap();
+		let extended = SolverSettings::parse("plies=1").unwrap().extend(configured);
+		assert_eq!(Valuation::MAX, extended.heuristic(&board));
+		assert_eq!(vec![extended.name()], extended.explain(&board).into_iter().map(|term| term.name).collect::<Vec<_>>());
+
+		let start = Board::start_position(9);
+		assert_eq!(extended.inner.heuristic(&start), extended.heuristic(&start));
+		assert_eq!(2, extended.explain(&start).len());
+		assert_eq!(Ok(SolverSettings { max_stragglers: 2, plies: 3 }), SolverSettings::parse("plies=3, max_stragglers=2"));
+		assert!(SolverSettings::parse("plies=deep").is_err());
+		assert!(SolverSettings::parse("width=3").is_err());
+	}
+
+	#[test]
+	fn no_win_from_start() {
+		let board = Board::start_position(9);
+		assert_eq!(None, Solver::new().solve(&board, 1));
+	}
+}
@@ -0,0 +1,172 @@
+use crate::board::*;
+use crate::hashes::*;
+
+/// The eight symmetries of the square board. Those that map the start position's White
+/// corners onto Black's also swap the colors, so the start position is fixed by every one.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Symmetry {
+	Identity,
+	/// Quarter turn counterclockwise
+	Rotate90,
+	Rotate180,
+	Rotate270,
+	/// Mirror left to right
+	FlipX,
+	/// Mirror top to bottom
+	FlipY,
+	/// Mirror in the diagonal through (0, 0)
+	Transpose,
+	/// Mirror in the diagonal through (0, board_size - 1)
+	AntiTranspose
+}
+
+impl Symmetry {
+	pub const ALL: [Symmetry; 8] = [
+		Symmetry::Identity, Symmetry::Rotate90, Symmetry::Rotate180, Symmetry::Rotate270,
+		Symmetry::FlipX, Symmetry::FlipY, Symmetry::Transpose, Symmetry::AntiTranspose
+	];
+
+	#[inline]
+	pub fn apply(&self, coord: Coord, board_size: i32) -> Coord {
+		let Coord(x, y) = coord;
+		let last = board_size - 1;
+		match self {
+			Symmetry::Identity => Coord(x, y),
+			Symmetry::Rotate90 => Coord(last - y, x),
+			Symmetry::Rotate180 => Coord(last - x, last - y),
+			Symmetry::Rotate270 => Coord(y, last - x),
+			Symmetry::FlipX => Coord(last - x, y),
+			Symmetry::FlipY => Coord(x, last - y),
+			Symmetry::Transpose => Coord(y, x),
+			Symmetry::AntiTranspose => Coord(last - y, last - x)
+		}
+	}
+
+	#[inline]
+	pub fn swaps_colors(&self) -> bool {
+		matches!(self, Symmetry::Rotate90 | Symmetry::Rotate270 | Symmetry::FlipX | Symmetry::FlipY)
+	}
+
+	#[inline]
+	pub fn apply_color(&self, color: Color) -> Color {
+		if self.swaps_colors() { color.opponent() } else { color }
+	}
+
+	pub fn inverse(&self) -> Symmetry {
+		match self {
+			Symmetry::Rotate90 => Symmetry::Rotate270,
+			Symmetry::Rotate270 => Symmetry::Rotate90,
+			symmetry => *symmetry
+		}
+	}
+}
+
+impl Move {
+	pub fn transform(&self, symmetry: Symmetry, board_size: i32) -> Move {
+		let color = symmetry.apply_color(self.color());
+		match self {
+			Move::Movement { active, pivot, conversions, .. } => Move::Movement {
+				color,
+				active: symmetry.apply(*active, board_size),
+				pivot: symmetry.apply(*pivot, board_size),
+				conversions: conversions.iter().map(|coord| symmetry.apply(*coord, board_size)).collect()
+			},
+			Move::Placement { at, .. } => Move::Placement { color, at: symmetry.apply(*at, board_size) }
+		}
+	}
+}
+
+impl Board {
+	pub fn transform(&self, symmetry: Symmetry) -> Board {
+		let transform_pieces = |color: Color| self.pieces_of(color).iter()
+			.map(|coord| symmetry.apply(*coord, self.board_size))
+			.collect();
+		let mut board = self.clone();
+		*board.pieces_of_mut(symmetry.apply_color(Color::White)) = transform_pieces(Color::White);
+		*board.pieces_of_mut(symmetry.apply_color(Color::Black)) = transform_pieces(Color::Black);
+		*board.reserve_of_mut(symmetry.apply_color(Color::White)) = self.white_reserve;
+		*board.reserve_of_mut(symmetry.apply_color(Color::Black)) = self.black_reserve;
+		board.whose_move = symmetry.apply_color(self.whose_move);
+		board.recompute_components();
+		board.zobrist_hash = board.compute_zobrist_hash();
+		board
+	}
+
+	/// Hash of `self.transform(symmetry)` without building the transformed board.
+	pub fn zobrist_hash_under(&self, symmetry: Symmetry) -> u64 {
+		let mut hash = reserve_hash(symmetry.apply_color(Color::White), self.white_reserve)
+			^ reserve_hash(symmetry.apply_color(Color::Black), self.black_reserve)
+			^ turn_hash(symmetry.apply_color(self.whose_move));
+		for color in [Color::White, Color::Black] {
+			for coord in self.pieces_of(color).iter() {
+				hash ^= piece_hash(symmetry.apply_color(color), symmetry.apply(*coord, self.board_size));
+			}
+		}
+		hash
+	}
+
+	/// The symmetry giving the smallest hash, which maps every symmetric version of this
+	/// position to the same board. If it swaps colors, values from White's point of view
+	/// must be negated when shared.
+	pub fn canonical_symmetry(&self) -> Symmetry {
+		Symmetry::ALL.into_iter().min_by_key(|symmetry| self.zobrist_hash_under(*symmetry)).unwrap()
+	}
+
+	/// A hash shared by all eight symmetric versions of this position.
+	pub fn canonical_hash(&self) -> u64 {
+		Symmetry::ALL.into_iter().map(|symmetry| self.zobrist_hash_under(symmetry)).min().unwrap()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::board::tests::random_positions;
+	use crate::perft::*;
+	use im::OrdSet;
+
+	#[test]
+	fn start_position_is_symmetric() {
+		let start = Board::start_position(9);
+		for symmetry in Symmetry::ALL {
+			let transformed = start.transform(symmetry);
+			assert_eq!(start, transformed, "{symmetry:?}");
+			assert_eq!(symmetry.apply_color(Color::White), transformed.whose_move);
+		}
+	}
+
+	#[test]
+	fn inverse_undoes_transform() {
+		for symmetry in Symmetry::ALL {
+			for coord in [Coord(0,0), Coord(1,5), Coord(8,3)] {
+				assert_eq!(coord, symmetry.inverse().apply(symmetry.apply(coord, 9), 9));
+			}
+		}
+	}
+
+	#[test]
+	fn moves_commute_with_symmetry() {
+		let board = random_positions(5, 1, 12).pop().unwrap();
+		let canonical_hash = board.canonical_hash();
+		for symmetry in Symmetry::ALL {
+			let transformed = board.transform(symmetry);
+			assert_eq!(canonical_hash, transformed.canonical_hash(), "{symmetry:?}");
+			assert_eq!(transformed.compute_zobrist_hash(), board.zobrist_hash_under(symmetry));
+
+			let expected: OrdSet<Move> = board.moves().map(|mov| mov.transform(symmetry, 9)).collect();
+			let actual: OrdSet<Move> = transformed.moves().collect();
+			assert_eq!(expected, actual, "{symmetry:?}");
+
+			let mov = board.moves().next().unwrap();
+			assert_eq!(board.apply(&mov).transform(symmetry), transformed.apply(&mov.transform(symmetry, 9)));
+			assert_eq!(perft(&board, 2), perft(&transformed, 2));
+		}
+	}
+
+	#[test]
+	fn incremental_hash_matches_full_hash() {
+		for board in random_positions(6, 1, 20) {
+			assert_eq!(board.compute_zobrist_hash(), board.zobrist_hash);
+		}
+	}
+}
@@ -0,0 +1,218 @@
+use crate::ai::*;
+use crate::board::*;
+use crate::game::*;
+use crate::notation::*;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub struct TrainingPosition {
+	pub features: Vec<f64>,
+	pub result: f64
+}
+
+/// Feature values by index, listing only the nonzero ones, with the game result.
+pub struct SparsePosition {
+	pub features: Vec<(usize, f64)>,
+	pub result: f64
+}
+
+impl SparsePosition {
+	#[inline]
+	pub fn eval(&self, weights: &[f64]) -> f64 {
+		self.features.iter().map(|(index, value)| weights[*index] * value).sum()
+	}
+}
+
+/// Gradient descent on the squared error between game results and the logistic of a weighted
+/// sum of features, with an L2 penalty of `regularization` on the weights.
+pub struct LogisticFitter {
+	pub learning_rate: f64,
+	pub iterations: usize,
+	pub regularization: f64
+}
+
+impl LogisticFitter {
+	pub fn fit(&self, n_features: usize, positions: &[SparsePosition]) -> Vec<f64> {
+		let n_positions = positions.len().max(1) as f64;
+		let mut weights = vec![0.0; n_features];
+		let mut gradient = vec![0.0; n_features];
+		for _ in 0..self.iterations {
+			for position in positions {
+				let prediction = sigmoid(position.eval(&weights));
+				let slope = -2.0 * (position.result - prediction) * prediction * (1.0 - prediction);
+				for (index, value) in position.features.iter() {
+					gradient[*index] += slope * value;
+				}
+			}
+			for (weight, slope) in weights.iter_mut().zip(gradient.iter_mut()) {
+				*weight -= self.learning_rate * (*slope / n_positions + self.regularization * *weight);
+				*slope = 0.0;
+			}
+		}
+		weights
+	}
+}
+
+pub struct TexelTuner {
+	pub learning_rate: f64,
+	pub iterations: usize,
+	pub skip_plies: usize
+}
+
+impl Default for TexelTuner {
+	fn default() -> Self {
+		TexelTuner { learning_rate: 1.0, iterations: 2000, skip_plies: 4 }
+	}
+}
+
+/// Reads a file of game records, checking that every move is legal and that games which
+/// ended on the board carry their actual result.
+pub fn load_corpus(path: impl AsRef<Path>) -> io::Result<Vec<GameRecord>> {
+	let text = fs::read_to_string(path)?;
+	let games = match parse_games(&text) {
+		Ok(("", games)) => games,
+		Ok((rest, _)) => return Err(io::Error::new(io::ErrorKind::InvalidData,
+			format!("Could not parse game record starting at {:?}", rest.lines().next().unwrap_or("")))),
+		Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+	};
+	for (game, record) in games.iter().enumerate() {
+		Game::from_record(record, DrawRules::default()).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, match err {
+			RecordError::IllegalMove(ply, reason) =>
+				format!("Game {} has an illegal move at ply {}: {reason:?}", game + 1, ply + 1),
+			RecordError::WrongResult(outcome) =>
+				format!("Game {} ends in {outcome:?}, not its recorded result", game + 1)
+		}))?;
+	}
+	Ok(games)
+}
+
+#[inline]
+fn sigmoid(x: f64) -> f64 {
+	1.0 / (1.0 + (-x).exp())
+}
+
+/// Calls `visit` with every non-terminal position of each decided game after the first
+/// `skip_plies` plies, and the result: 1.0 for a White win and 0.0 for a Black win. Games
+/// that `Game::from_record` rejects are skipped.
+pub fn for_each_decided_position(records: &[GameRecord], skip_plies: usize, mut visit: impl FnMut(&Board, f64)) {
+	for record in records {
+		let result = match record.winner {
+			Some(Color::White) => 1.0,
+			Some(Color::Black) => 0.0,
+			None => continue
+		};
+		let Ok(game) = Game::from_record(record, DrawRules::default()) else { continue };
+		for (_, board) in game.iter().skip(skip_plies).filter(|(_, board)| board.winner().is_none()) {
+			visit(board, result);
+		}
+	}
+}
+
+impl TexelTuner {
+	/// The raw value of each term for every position `for_each_decided_position` visits.
+	pub fn extract_positions(&self, records: &[GameRecord], heuristic: &LinearCombinationHeuristic) -> Vec<TrainingPosition> {
+		let mut positions = vec![];
+		for_each_decided_position(records, self.skip_plies, |board, result| {
+			let features = heuristic.terms.iter()
+				.map(|(_, term)| term.heuristic(board) as f64)
+				.collect();
+			positions.push(TrainingPosition { features, result });
+		});
+		positions
+	}
+
+	/// Fits one weight per feature with an unregularized `LogisticFitter`. Features are
+	/// rescaled to unit RMS while fitting so that terms of very different magnitudes converge
+	/// at the same rate.
+	pub fn fit(&self, positions: &[TrainingPosition]) -> Vec<f64> {
+		let n_features = positions.first().map_or(0, |position| position.features.len());
+		let n_positions = positions.len() as f64;
+		let scales: Vec<f64> = (0..n_features).map(|i| {
+			let mean_square = positions.iter().map(|position| position.features[i].powi(2)).sum::<f64>() / n_positions;
+			if mean_square > 0.0 { mean_square.sqrt() } else { 1.0 }
+		}).collect();
+		let scaled: Vec<_> = positions.iter().map(|position| SparsePosition {
+			features: position.features.iter().zip(&scales).map(|(feature, scale)| feature / scale).enumerate().collect(),
+			result: position.result
+		}).collect();
+		let fitter = LogisticFitter { learning_rate: self.learning_rate, iterations: self.iterations, regularization: 0.0 };
+		fitter.fit(n_features, &scaled).iter().zip(&scales).map(|(weight, scale)| weight / scale).collect()
+	}
+}
+
+pub fn mean_squared_error(positions: &[TrainingPosition], weights: &[f64]) -> f64 {
+	positions.iter().map(|position| {
+		let eval: f64 = position.features.iter().zip(weights).map(|(feature, weight)| feature * weight).sum();
+		(position.result - sigmoid(eval)).powi(2)
+	}).sum::<f64>() / positions.len() as f64
+}
+
+/// Magnitude of the largest weight written by `to_valuation_weights`.
+pub const VALUATION_WEIGHT_SCALE: f64 = 1000.0;
+
+/// Scales fitted weights so the largest has magnitude `VALUATION_WEIGHT_SCALE` and rounds them.
+/// Only the ratios between weights matter to the search, but weights under 1/2000 of the
+/// largest round to 0.
+pub fn to_valuation_weights(weights: &[f64]) -> Vec<Valuation> {
+	let largest = weights.iter().fold(0.0f64, |acc, weight| acc.max(weight.abs()));
+	if largest == 0.0 || !largest.is_finite() {
+		return vec![0; weights.len()];
+	}
+	weights.iter().map(|weight| (weight / largest * VALUATION_WEIGHT_SCALE).round() as Valuation).collect()
+}
+
+pub fn write_weights(path: impl AsRef<Path>, weights: &[Valuation]) -> io::Result<()> {
+	let lines: Vec<String> = weights.iter().map(|weight| weight.to_string()).collect();
+	fs::write(path, lines.join("\n") + "\n")
+}
+
+pub fn read_weights(path: impl AsRef<Path>) -> io::Result<Vec<Valuation>> {
+	fs::read_to_string(path)?
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(|line| line.parse().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{line:?}: {err}"))))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fit_follows_correlated_feature() {
+		let positions: Vec<_> = (0..100).map(|i| {
+			let signal = if i % 2 == 0 { 1000.0 } else { -1000.0 };
+			let noise = ((i * 7) % 5) as f64 - 2.0;
+			TrainingPosition { features: vec![signal, noise], result: if signal > 0.0 { 1.0 } else { 0.0 } }
+		}).collect();
+		let tuner = TexelTuner { learning_rate: 1.0, iterations: 200, skip_plies: 0 };
+		let weights = tuner.fit(&positions);
+		assert!(weights[0] > 0.0);
+		assert!(weights[0] * 1000.0 > weights[1].abs() * 2.0);
+		assert!(mean_squared_error(&positions, &weights) < mean_squared_error(&positions, &[0.0, 0.0]));
+	}
+
+	#[test]
+	fn valuation_weights_keep_ratios() {
+		assert_eq!(vec![200, 1000, -400], to_valuation_weights(&[0.01, 0.05, -0.02]));
+		assert_eq!(vec![1000, 0], to_valuation_weights(&[2.0, 1e-7]));
+		assert_eq!(vec![0, 0], to_valuation_weights(&[0.0, 0.0]));
+	}
+
+	#[test]
+	fn corpus_checks_moves() {
+		let path = std::env::temp_dir().join(format!("quorum-corpus-{}.txt", std::process::id()));
+		fs::write(&path, "1. 1一5三 1九3五\n0-1\n\n1. 1一5三 1一5三\n0-1\n").unwrap();
+		let err = load_corpus(&path).unwrap_err();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(io::ErrorKind::InvalidData, err.kind());
+		assert_eq!("Game 2 has an illegal move at ply 2: ActiveNotOwned", err.to_string());
+
+		let records = parse_games("1. 1一5三 1一5三\n0-1\n").unwrap().1;
+		assert_eq!(RecordError::IllegalMove(1, IllegalMoveReason::ActiveNotOwned), Game::from_record(&records[0], DrawRules::default()).unwrap_err());
+		assert!(TexelTuner { skip_plies: 0, ..TexelTuner::default() }.extract_positions(&records, &LinearCombinationHeuristic { terms: vec![] }).is_empty());
+	}
+}